@@ -1,23 +1,50 @@
 use llama_cpp_sys;
-use llama_cpp_sys::llama_token_data;
-use std::ffi::c_char;
+use std::cell::OnceCell;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
 
+mod llama_candidates;
+mod llama_chat_template;
 mod llama_context;
 mod llama_context_config;
+mod llama_detokenizer;
+mod llama_embeddings;
 mod llama_error;
+mod llama_gguf;
+mod llama_grammar;
+mod llama_json_schema;
+mod llama_logit_bias;
+mod llama_logits;
+mod llama_model;
 mod llama_sample_params;
+mod llama_sampler;
+mod llama_score;
+mod llama_session_state;
 mod llama_token;
 mod llama_token_sequence;
+mod llama_tokenize_options;
+mod llama_vocab;
 
 pub use self::llama_error::LError;
 
+/// The number of worker threads used when none is given: one for each CPU available to the process.
+pub(crate) fn default_thread_count() -> usize {
+    thread::available_parallelism().map(|count| count.get()).unwrap_or(4)
+}
+
 /// You construct a context using these parameters
 pub struct LContextConfig {
     model_path: PathBuf,
     params: llama_cpp_sys::llama_context_params,
+    progress: Option<LLoadProgress>,
     pub seed: u32,
     pub n_ctx: i32,
+
+    /// The maximum number of tokens passed to llama.cpp in a single evaluation, which sizes its compute buffer.
+    /// Longer steps are split into batches of this size; with `logits_all`, only the last batch keeps its logits.
+    pub n_batch: i32,
     pub n_parts: i32,
     pub f16_kv: bool,
     pub use_mlock: bool,
@@ -28,35 +55,415 @@ pub struct LContextConfig {
     pub low_vram: bool,
 }
 
+/// Load progress reporting state, passed to the native progress callback as user data.
+struct LLoadProgress {
+    callback: Box<dyn FnMut(f32) -> bool + Send>,
+    cancelled: bool,
+}
+
 /// Parameters for sampling the context
 #[derive(Copy, Clone, Debug)]
 pub struct LSampleParams {
     pub top_k: i32,
     pub top_p: f32,
+
+    /// The temperature; 0 or less always samples the most likely token
     pub temp: f32,
     pub repeat_penalty: f32,
     pub repeat_history_length: usize,
     pub tfs_z: f32,
     pub typical_p: f32,
+
+    /// Keep only tokens whose probability is at least min_p times that of the most likely token; 0 disables it
+    pub min_p: f32,
+
+    /// Subtract this times the number of times a token appears in the history from its logit
+    pub frequency_penalty: f32,
+
+    /// Subtract this from the logit of every token that appears in the history
+    pub presence_penalty: f32,
+
+    /// If false, the newline token is exempt from the repetition, frequency and presence penalties
+    pub penalize_newline: bool,
+
+    /// How the next token is chosen after the repetition penalty is applied
+    pub mode: LSampleMode,
+}
+
+/// The sampling algorithm used by `LContext::sample`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LSampleMode {
+    /// Apply top-k, tail free, typical and top-p filtering and the temperature, then sample.
+    Standard,
+
+    /// Mirostat v1; tau is the target entropy, eta the learning rate, and m the number of
+    /// candidates used to estimate the distribution (100 in the paper).
+    Mirostat { tau: f32, eta: f32, m: i32 },
+
+    /// Mirostat v2; tau is the target entropy, and eta the learning rate.
+    MirostatV2 { tau: f32, eta: f32 },
 }
 
-/// A context contains the loaded model
+/// Adjustments added to the logits of specific tokens before sampling; see `LContext::set_logit_bias`.
+/// This is kept apart from `LSampleParams` so that the params remain `Copy`.
+#[derive(Clone, Debug, Default)]
+pub struct LLogitBias {
+    biases: HashMap<llama_cpp_sys::llama_token, f32>,
+
+    /// Never sample the end of stream token, so generation only stops at the token limit or a stop sequence.
+    /// This is ignored while a grammar is set, since the grammar only allows EOS once its output is complete.
+    pub ban_eos: bool,
+}
+
+/// A single candidate for the next token; see `LCandidates`.
+/// This has the same layout as the native `llama_token_data`, so candidates can be passed to llama.cpp without copying.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LCandidate {
+    pub id: llama_cpp_sys::llama_token,
+    pub logit: f32,
+
+    /// The probability of the token; only valid after `LCandidates::softmax`.
+    pub p: f32,
+}
+
+/// The candidates for the next token, which samplers adjust and filter before a token is chosen.
+#[derive(Clone, Debug, Default)]
+pub struct LCandidates {
+    data: Vec<LCandidate>,
+    sorted: bool,
+}
+
+/// A step of an `LSamplerChain`, which adjusts or filters the candidates for the next token.
+/// Any `FnMut(&LContext, &mut LCandidates) -> Result<(), LError>` closure is a sampler.
+pub trait LSampler: Send {
+    fn apply(&mut self, context: &LContext, candidates: &mut LCandidates) -> Result<(), LError>;
+}
+
+/// An ordered list of samplers; see `LContext::sample_with_chain`.
+#[derive(Default)]
+pub struct LSamplerChain {
+    samplers: Vec<Box<dyn LSampler>>,
+}
+
+/// Keep only the k most likely candidates.
+#[derive(Copy, Clone, Debug)]
+pub struct LTopK(pub i32);
+
+/// Tail free sampling with parameter z; 1 disables it.
+#[derive(Copy, Clone, Debug)]
+pub struct LTailFree(pub f32);
+
+/// Locally typical sampling with parameter p; 1 disables it.
+#[derive(Copy, Clone, Debug)]
+pub struct LTypical(pub f32);
+
+/// Keep the most likely candidates whose cumulative probability is at least p.
+#[derive(Copy, Clone, Debug)]
+pub struct LTopP(pub f32);
+
+/// Keep the candidates whose probability is at least p times the probability of the most likely candidate; 0 disables it.
+#[derive(Copy, Clone, Debug)]
+pub struct LMinP(pub f32);
+
+/// Divide the logits by the temperature; a temperature of 0 or less keeps only the most likely candidate.
+#[derive(Copy, Clone, Debug)]
+pub struct LTemperature(pub f32);
+
+/// The repetition, frequency and presence penalties over the most recently sampled tokens.
+/// Tokens set with `LContext::set_penalty_exempt_tokens` are not penalized.
+#[derive(Copy, Clone, Debug)]
+pub struct LPenalties {
+    pub repeat_penalty: f32,
+    pub frequency_penalty: f32,
+    pub presence_penalty: f32,
+
+    /// The number of recently sampled tokens to penalize; at most the context size.
+    pub history_length: usize,
+    pub penalize_newline: bool,
+}
+
+/// Who a chat message is from
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LChatRole {
+    /// Instructions for the assistant, usually only at the start of the conversation
+    System,
+    User,
+    Assistant,
+
+    /// The result of a tool the assistant asked to run
+    Tool,
+}
+
+/// A single message in a conversation; see `LChatTemplate`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LChatMessage {
+    pub role: LChatRole,
+    pub content: String,
+}
+
+/// Formats a conversation as a prompt in the syntax a chat model was trained on.
+/// More variants may be added, and `Jinja` only exists with the `jinja` feature, so matches need a wildcard arm.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum LChatTemplate {
+    /// `[INST] <<SYS>>\n{system}\n<</SYS>>\n\n{user} [/INST] {assistant} </s><s>[INST] ...`
+    Llama2,
+
+    /// `<|im_start|>{role}\n{content}<|im_end|>\n`, as used by many fine tunes
+    ChatML,
+
+    /// `### Instruction:\n{user}\n\n### Response:\n{assistant}\n\n`
+    Alpaca,
+
+    /// `USER: {user}\nASSISTANT: {assistant}</s>\n`
+    Vicuna,
+
+    /// `[INST] {user} [/INST]{assistant}</s>[INST] ...`, with the system message before the first user message
+    Mistral,
+
+    /// `<|user|>\n{user}</s>\n<|assistant|>\n{assistant}</s>\n`
+    Zephyr,
+
+    /// A Jinja template in the format of Hugging Face `chat_template`, like the one stored in GGUF models.
+    #[cfg(feature = "jinja")]
+    Jinja { source: String, bos_token: String, eos_token: String },
+}
+
+/// A grammar restricting sampling to the text it matches; see `LGrammar::parse`.
+#[derive(Clone, Debug)]
+pub struct LGrammar {
+    rules: Vec<Vec<llama_cpp_sys::llama_grammar_element>>,
+    root: usize,
+}
+
+/// The subset of JSON Schema that can be compiled to a grammar by `LGrammar::from_json_schema`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LJsonSchema {
+    /// An object; properties are generated in the order given, with the required ones first.
+    Object {
+        properties: Vec<(String, LJsonSchema)>,
+        required: Vec<String>,
+    },
+
+    Array {
+        items: Box<LJsonSchema>,
+    },
+
+    /// A string, optionally matching a regular expression; see `LJsonSchema::to_gbnf` for the supported syntax.
+    String {
+        pattern: Option<String>,
+    },
+
+    Number,
+    Integer,
+    Boolean,
+    Null,
+
+    /// One of a fixed set of values, each given as JSON text; eg. `"\"red\""` or `"42"`.
+    Enum(Vec<String>),
+
+    /// A value matching any one of the schemas
+    OneOf(Vec<LJsonSchema>),
+}
+
+/// The native parse state of a grammar, which advances as tokens are sampled.
+struct LGrammarState {
+    grammar: LGrammar,
+    state: *mut llama_cpp_sys::llama_grammar,
+}
+
+/// A model contains the loaded weights.
+/// Models are reference counted; cloning a model is cheap, and any number of contexts
+/// can be created from the same model without loading the weights again.
+#[derive(Clone)]
+pub struct LModel {
+    handle: Arc<LModelHandle>,
+}
+
+/// The native model; freed when the last `LModel` referring to it is dropped.
+struct LModelHandle {
+    model_path: PathBuf,
+    model: *mut llama_cpp_sys::llama_model,
+}
+
+/// A context is a single inference session over a loaded model, with its own KV cache.
 pub struct LContext {
     steps: usize,
-    model: *mut llama_cpp_sys::llama_model,
+    model: LModel,
     ctx: *mut llama_cpp_sys::llama_context,
+    evaluated: LTokenSequence,
+
+    /// True if the context keeps the logits of every token in a step, rather than just the last one
+    logits_all: bool,
+
+    /// True if the context computes embeddings
+    embedding: bool,
+
+    /// The number of tokens evaluated by the last batch of the last step
+    batch_len: usize,
+
+    n_batch: usize,
+
+    candidates: LCandidates,
+    token_history: Vec<llama_cpp_sys::llama_token>,
+
+    /// The mirostat running estimate of the maximum surprise, if mirostat sampling has started
+    mirostat_mu: Option<f32>,
+
+    /// The grammar sampled tokens must match, if any
+    grammar: Option<LGrammarState>,
+
+    /// Added to the logits before sampling, if any
+    logit_bias: Option<LLogitBias>,
+
+    /// Tokens exempt from the repetition, frequency and presence penalties
+    penalty_exempt: Vec<llama_cpp_sys::llama_token>,
+
+    /// The text and id of each token recognised by `LTokenizeOptions::parse_special`, built on first use
+    special_tokens: OnceCell<Vec<(String, llama_cpp_sys::llama_token)>>,
+
+    /// Token ids by vocabulary text, built on the first `LVocab::find_text`
+    vocab_texts: OnceCell<HashMap<String, llama_cpp_sys::llama_token>>,
+
+    /// Token ids by piece, built on the first `LVocab::find_piece`
+    vocab_pieces: OnceCell<HashMap<Vec<u8>, llama_cpp_sys::llama_token>>,
+}
 
-    // TODO: Split this into a new file
-    candidates: Vec<llama_token_data>,
+/// An in-memory copy of the evaluated state of a context, including its KV cache.
+/// See `LContext::snapshot` and `LContext::restore`.
+#[derive(Clone)]
+pub struct LSessionState {
+    data: Vec<u8>,
+    model_path: PathBuf,
+    n_ctx: i32,
+    n_vocab: i32,
+    steps: usize,
+    batch_len: usize,
+    evaluated: LTokenSequence,
     token_history: Vec<llama_cpp_sys::llama_token>,
-    token_buffer: Vec<c_char>,
+    mirostat_mu: Option<f32>,
+}
+
+/// Performance counters for a context, as reported by llama.cpp.
+#[derive(Copy, Clone, Debug)]
+pub struct LTimings {
+    pub load_ms: f64,
+    pub sample_ms: f64,
+    pub sample_count: usize,
+
+    /// Time spent evaluating batches of more than one token, which is usually the prompt.
+    pub prompt_eval_ms: f64,
+    pub prompt_eval_count: usize,
+
+    /// Time spent evaluating single tokens, which is usually generation.
+    pub eval_ms: f64,
+    pub eval_count: usize,
+}
+
+/// The logits of every token evaluated by the last batch of a context with `logits_all` set; see `LContext::all_logits`.
+/// Rows are indexed by position in the context, and columns by token id.
+#[derive(Copy, Clone, Debug)]
+pub struct LLogits<'a> {
+    data: &'a [f32],
+    n_vocab: usize,
+    start: usize,
+}
+
+/// A sampled token with its log-probability, and the most likely alternatives; see `LContext::sample_with_logprobs`.
+/// Log-probabilities are taken from the model's output distribution, before any sampling adjustments.
+#[derive(Clone, Debug)]
+pub struct LSampleResult {
+    pub token: LToken,
+    pub logprob: f32,
+
+    /// The most likely tokens and their log-probabilities, most likely first; these may include token.
+    pub top_logprobs: Vec<(LToken, f32)>,
+}
+
+/// Options for `LContext::embed`
+#[derive(Copy, Clone, Debug)]
+pub struct LEmbedOptions {
+    pub worker_thread_count: usize,
+
+    /// Scale the embedding to unit length, so the dot product of two embeddings is their cosine similarity
+    pub normalize: bool,
+}
+
+/// How likely a token sequence is under the model; see `LContext::score` and `LContext::perplexity`.
+#[derive(Clone, Debug)]
+pub struct LScoreResult {
+    /// The log-probability of each scored token given the tokens before it
+    pub token_logprobs: Vec<f32>,
+
+    /// The sum of token_logprobs
+    pub logprob: f64,
+
+    /// exp of the mean negative log-probability of the scored tokens
+    pub perplexity: f64,
+}
+
+/// Converts a stream of tokens to text, buffering the bytes of a UTF-8 character split across tokens
+/// until the character is complete.
+#[derive(Clone, Debug, Default)]
+pub struct LDetokenizer {
+    pending: Vec<u8>,
 }
 
 /// A text sequence is represented as a sequence of tokens for inference.
 /// A `Context` can convert a token into the associated text sequence.
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct LToken(llama_cpp_sys::llama_token);
 
+/// The vocabulary of the model of a context; see `LContext::vocab`.
+pub struct LVocab<'a> {
+    context: &'a LContext,
+}
+
+/// A single token in the vocabulary
+#[derive(Clone, Debug)]
+pub struct LVocabEntry {
+    pub token: LToken,
+
+    /// The text of the token as stored in the vocabulary; eg. `▁Hello` or `<0x0A>`.
+    pub text: String,
+
+    /// The bytes the token produces in generated text; eg. ` Hello` or `\n`.
+    pub piece: Vec<u8>,
+    pub score: f32,
+    pub token_type: LTokenType,
+}
+
+/// The kind of a token in the vocabulary
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LTokenType {
+    Undefined,
+    Normal,
+    Unknown,
+
+    /// A token with special meaning to the model, like BOS and EOS
+    Control,
+    UserDefined,
+    Unused,
+
+    /// A single byte, for text that is not otherwise in the vocabulary
+    Byte,
+}
+
+/// Options for `LContext::tokenize_with`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LTokenizeOptions {
+    /// Start with the beginning of stream token; only the first part of a prompt should have one.
+    pub add_bos: bool,
+
+    /// Convert the text of control and user-defined tokens, like `<s>` or `<|im_start|>`, into those tokens
+    /// instead of tokenizing it as plain text.
+    pub parse_special: bool,
+
+    /// End with the end of stream token
+    pub add_eos: bool,
+}
+
 /// A set of tokens representing a block of text.
 #[derive(Clone)]
 pub struct LTokenSequence {
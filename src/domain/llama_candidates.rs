@@ -0,0 +1,127 @@
+use crate::domain::{LCandidate, LCandidates};
+use crate::LToken;
+use llama_cpp_sys::{llama_token, llama_token_data, llama_token_data_array};
+use std::mem;
+use std::slice;
+
+// Candidates are passed to llama.cpp as llama_token_data, so the layouts must match.
+const _: () = assert!(mem::size_of::<LCandidate>() == mem::size_of::<llama_token_data>());
+const _: () = assert!(mem::align_of::<LCandidate>() == mem::align_of::<llama_token_data>());
+
+impl LCandidate {
+    pub fn token(&self) -> LToken {
+        LToken::from(self.id)
+    }
+}
+
+impl LCandidates {
+    pub fn new() -> LCandidates {
+        Default::default()
+    }
+
+    /// One candidate for each logit, where the token id of each candidate is the index of its logit.
+    pub fn from_logits(logits: &[f32]) -> LCandidates {
+        let mut candidates = LCandidates::new();
+        candidates.fill(logits);
+        candidates
+    }
+
+    /// Replace the candidates with one for each logit, reusing the existing allocation.
+    pub(crate) fn fill(&mut self, logits: &[f32]) {
+        self.data.clear();
+        self.data.extend(logits.iter().enumerate().map(|(id, logit)| LCandidate {
+            id: id as llama_token,
+            logit: *logit,
+            p: 0f32,
+        }));
+        self.sorted = false;
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// True if the candidates are known to be in order of descending logit.
+    pub fn is_sorted(&self) -> bool {
+        self.sorted
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, LCandidate> {
+        self.data.iter()
+    }
+
+    pub fn as_slice(&self) -> &[LCandidate] {
+        &self.data
+    }
+
+    /// Modify the candidates in place; they are no longer considered sorted afterwards.
+    pub fn as_mut_slice(&mut self) -> &mut [LCandidate] {
+        self.sorted = false;
+        &mut self.data
+    }
+
+    /// Sort the candidates by descending logit.
+    pub fn sort(&mut self) {
+        if !self.sorted {
+            self.data.sort_by(|a, b| b.logit.total_cmp(&a.logit));
+            self.sorted = true;
+        }
+    }
+
+    /// Sort the candidates, and set the probability of each to the softmax of the logits.
+    pub fn softmax(&mut self) {
+        self.sort();
+        let max_logit = match self.data.first() {
+            Some(candidate) => candidate.logit,
+            None => return,
+        };
+        let mut sum = 0f32;
+        for candidate in self.data.iter_mut() {
+            candidate.p = (candidate.logit - max_logit).exp();
+            sum += candidate.p;
+        }
+        for candidate in self.data.iter_mut() {
+            candidate.p /= sum;
+        }
+    }
+
+    /// Keep the candidates whose probability is at least p times the probability of the most likely
+    /// candidate, and at least min_keep candidates. The candidates are sorted and their probabilities set.
+    pub fn min_p(&mut self, p: f32, min_keep: usize) {
+        if p <= 0f32 || self.data.is_empty() {
+            return;
+        }
+        self.softmax();
+        let threshold = self.data[0].p * p;
+        let keep = self.data.iter().take_while(|candidate| candidate.p >= threshold).count();
+        self.data.truncate(keep.max(min_keep));
+    }
+
+    /// Keep only the first length candidates.
+    pub fn truncate(&mut self, length: usize) {
+        self.data.truncate(length);
+    }
+
+    /// Keep only the candidates for which f returns true, preserving their order.
+    pub fn retain(&mut self, f: impl FnMut(&LCandidate) -> bool) {
+        self.data.retain(f);
+    }
+
+    /// Pass the candidates to a native sampling function.
+    /// The native samplers filter by reducing the size of the array, and may reorder the candidates in place.
+    pub(crate) unsafe fn with_native<R>(&mut self, f: impl FnOnce(*mut llama_token_data_array) -> R) -> R {
+        let mut array = llama_token_data_array {
+            data: self.data.as_mut_ptr() as *mut llama_token_data,
+            size: self.data.len(),
+            sorted: self.sorted,
+        };
+        let result = f(&mut array);
+        self.data.truncate(array.size);
+        self.sorted = array.sorted;
+        result
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{LChatMessage, LChatRole, LChatTemplate, LContext, LError};
+#[cfg(feature = "jinja")]
+use minijinja::{context, Environment, ErrorKind};
+
+impl LChatMessage {
+    pub fn new(role: LChatRole, content: impl Into<String>) -> LChatMessage {
+        LChatMessage {
+            role,
+            content: content.into(),
+        }
+    }
+
+    pub fn system(content: impl Into<String>) -> LChatMessage {
+        LChatMessage::new(LChatRole::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> LChatMessage {
+        LChatMessage::new(LChatRole::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> LChatMessage {
+        LChatMessage::new(LChatRole::Assistant, content)
+    }
+
+    pub fn tool(content: impl Into<String>) -> LChatMessage {
+        LChatMessage::new(LChatRole::Tool, content)
+    }
+}
+
+impl LChatRole {
+    /// The name of the role in ChatML and Jinja templates
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LChatRole::System => "system",
+            LChatRole::User => "user",
+            LChatRole::Assistant => "assistant",
+            LChatRole::Tool => "tool",
+        }
+    }
+}
+
+impl LChatTemplate {
+    /// The chat template for the model of context; see `from_source` for how a `tokenizer.chat_template`
+    /// stored in the model file is used. Models without a usable template use ChatML if the vocabulary
+    /// has its tokens, and Llama2 if not.
+    pub fn from_context(context: &LContext) -> Result<LChatTemplate, LError> {
+        if let Some(source) = context.model().chat_template()? {
+            let vocab = context.vocab();
+            let bos_token = vocab.text(&vocab.bos()).unwrap_or_default();
+            let eos_token = vocab.text(&vocab.eos()).unwrap_or_default();
+            if let Some(template) = LChatTemplate::from_source(&source, &bos_token, &eos_token) {
+                return Ok(template);
+            }
+        }
+        if context.vocab().find_text("<|im_start|>").is_some() {
+            return Ok(LChatTemplate::ChatML);
+        }
+        Ok(LChatTemplate::Llama2)
+    }
+
+    /// The template for a Hugging Face style Jinja chat template.
+    /// With the `jinja` feature the source is used directly, as long as it can render a simple conversation;
+    /// templates that need features the renderer lacks, and all templates without the feature, are matched to
+    /// one of the built-in templates with `detect` instead.
+    pub fn from_source(source: &str, bos_token: &str, eos_token: &str) -> Option<LChatTemplate> {
+        #[cfg(feature = "jinja")]
+        {
+            let template = LChatTemplate::Jinja {
+                source: source.to_string(),
+                bos_token: bos_token.to_string(),
+                eos_token: eos_token.to_string(),
+            };
+            let conversation = [
+                LChatMessage::user("Hello"),
+                LChatMessage::assistant("Hi"),
+                LChatMessage::user("How are you?"),
+            ];
+            if template.render(&conversation, true).is_ok() {
+                return Some(template);
+            }
+        }
+        #[cfg(not(feature = "jinja"))]
+        let _ = (bos_token, eos_token);
+        LChatTemplate::detect(source)
+    }
+
+    /// The built-in template that produces the same format as a Jinja chat template, judged by the markers it uses.
+    pub fn detect(source: &str) -> Option<LChatTemplate> {
+        if source.contains("<|im_start|>") {
+            Some(LChatTemplate::ChatML)
+        } else if source.contains("<|user|>") {
+            Some(LChatTemplate::Zephyr)
+        } else if source.contains("<<SYS>>") {
+            Some(LChatTemplate::Llama2)
+        } else if source.contains("[INST]") {
+            Some(LChatTemplate::Mistral)
+        } else if source.contains("### Instruction") {
+            Some(LChatTemplate::Alpaca)
+        } else if source.contains("USER:") {
+            Some(LChatTemplate::Vicuna)
+        } else {
+            None
+        }
+    }
+
+    /// Format messages as a prompt. If add_generation_prompt is set, the prompt ends with the start of an
+    /// assistant message, so the model generates the reply.
+    /// The prompt does not start with BOS, since the tokenizer adds it, but it can contain control tokens like
+    /// `</s>` between turns; tokenize it with `LTokenizeOptions::parse_special` set.
+    pub fn render(&self, messages: &[LChatMessage], add_generation_prompt: bool) -> Result<String, LError> {
+        let mut prompt = String::new();
+        match self {
+            LChatTemplate::Llama2 => {
+                let mut system = None;
+                for (index, message) in messages.iter().enumerate() {
+                    match message.role {
+                        LChatRole::System => system = Some(message.content.trim()),
+                        LChatRole::User => {
+                            if index > 0 && system.is_none() {
+                                prompt.push_str("<s>");
+                            }
+                            prompt.push_str("[INST] ");
+                            if let Some(system) = system.take() {
+                                prompt.push_str(&format!("<<SYS>>\n{}\n<</SYS>>\n\n", system));
+                            }
+                            prompt.push_str(&format!("{} [/INST]", message.content.trim()));
+                        }
+                        LChatRole::Assistant => prompt.push_str(&format!(" {} </s>", message.content.trim())),
+                        LChatRole::Tool => return Err(self.unsupported_role(message.role)),
+                    }
+                }
+            }
+            LChatTemplate::Mistral => {
+                let mut system = None;
+                for message in messages.iter() {
+                    match message.role {
+                        LChatRole::System => system = Some(message.content.trim()),
+                        LChatRole::User => match system.take() {
+                            Some(system) => prompt.push_str(&format!("[INST] {}\n\n{} [/INST]", system, message.content.trim())),
+                            None => prompt.push_str(&format!("[INST] {} [/INST]", message.content.trim())),
+                        },
+                        LChatRole::Assistant => prompt.push_str(&format!("{}</s>", message.content.trim())),
+                        LChatRole::Tool => return Err(self.unsupported_role(message.role)),
+                    }
+                }
+            }
+            LChatTemplate::ChatML => {
+                for message in messages.iter() {
+                    prompt.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", message.role.as_str(), message.content));
+                }
+                if add_generation_prompt {
+                    prompt.push_str("<|im_start|>assistant\n");
+                }
+            }
+            LChatTemplate::Alpaca => {
+                for message in messages.iter() {
+                    match message.role {
+                        LChatRole::System => prompt.push_str(&format!("{}\n\n", message.content)),
+                        LChatRole::User => prompt.push_str(&format!("### Instruction:\n{}\n\n", message.content)),
+                        LChatRole::Assistant => prompt.push_str(&format!("### Response:\n{}\n\n", message.content)),
+                        LChatRole::Tool => return Err(self.unsupported_role(message.role)),
+                    }
+                }
+                if add_generation_prompt {
+                    prompt.push_str("### Response:\n");
+                }
+            }
+            LChatTemplate::Vicuna => {
+                for message in messages.iter() {
+                    match message.role {
+                        LChatRole::System => prompt.push_str(&format!("{}\n\n", message.content)),
+                        LChatRole::User => prompt.push_str(&format!("USER: {}\n", message.content)),
+                        LChatRole::Assistant => prompt.push_str(&format!("ASSISTANT: {}</s>\n", message.content)),
+                        LChatRole::Tool => return Err(self.unsupported_role(message.role)),
+                    }
+                }
+                if add_generation_prompt {
+                    prompt.push_str("ASSISTANT:");
+                }
+            }
+            LChatTemplate::Zephyr => {
+                for message in messages.iter() {
+                    if message.role == LChatRole::Tool {
+                        return Err(self.unsupported_role(message.role));
+                    }
+                    prompt.push_str(&format!("<|{}|>\n{}</s>\n", message.role.as_str(), message.content));
+                }
+                if add_generation_prompt {
+                    prompt.push_str("<|assistant|>\n");
+                }
+            }
+            #[cfg(feature = "jinja")]
+            LChatTemplate::Jinja {
+                source,
+                bos_token,
+                eos_token,
+            } => {
+                prompt = render_jinja(source, bos_token, eos_token, messages, add_generation_prompt)?;
+            }
+        }
+        Ok(prompt)
+    }
+
+    /// Text that marks the end of the assistant's turn when the model does not generate EOS.
+    /// Jinja templates use the stop sequences of the built-in template that `detect` matches them to, if any.
+    pub fn stop_sequences(&self) -> Vec<String> {
+        let stop_sequences: &[&str] = match self {
+            LChatTemplate::Llama2 | LChatTemplate::Mistral => &["[INST]"],
+            LChatTemplate::ChatML => &["<|im_end|>", "<|im_start|>"],
+            LChatTemplate::Alpaca => &["### Instruction:"],
+            LChatTemplate::Vicuna => &["USER:"],
+            LChatTemplate::Zephyr => &["<|user|>"],
+            #[cfg(feature = "jinja")]
+            LChatTemplate::Jinja { source, .. } => {
+                return LChatTemplate::detect(source)
+                    .map(|template| template.stop_sequences())
+                    .unwrap_or_default()
+            }
+        };
+        stop_sequences.iter().map(|stop| stop.to_string()).collect()
+    }
+
+    fn unsupported_role(&self, role: LChatRole) -> LError {
+        LError::ChatTemplateError(format!("the {:?} chat template does not support {} messages", self, role.as_str()))
+    }
+}
+
+/// Render a Hugging Face style chat template, which is given `messages`, `add_generation_prompt`,
+/// `bos_token` and `eos_token`, and can call `raise_exception` to reject a conversation.
+#[cfg(feature = "jinja")]
+fn render_jinja(source: &str, bos_token: &str, eos_token: &str, messages: &[LChatMessage], add_generation_prompt: bool) -> Result<String, LError> {
+    let template_error = |err: minijinja::Error| LError::ChatTemplateError(format!("failed to render chat template: {}", err));
+    // Match the whitespace handling of Hugging Face transformers, and support the Python string methods
+    // that many templates use, like `.strip()`
+    let mut environment = Environment::new();
+    environment.set_trim_blocks(true);
+    environment.set_lstrip_blocks(true);
+    environment.set_unknown_method_callback(minijinja_contrib::pycompat::unknown_method_callback);
+    environment.add_function("raise_exception", |message: String| -> Result<String, minijinja::Error> {
+        Err(minijinja::Error::new(ErrorKind::InvalidOperation, message))
+    });
+    environment.add_template("chat_template", source).map_err(template_error)?;
+
+    let messages: Vec<_> = messages
+        .iter()
+        .map(|message| context! { role => message.role.as_str(), content => message.content })
+        .collect();
+    let prompt = environment
+        .get_template("chat_template")
+        .and_then(|template| template.render(context! { messages, add_generation_prompt, bos_token, eos_token }))
+        .map_err(template_error)?;
+
+    // The tokenizer adds BOS to the start of the prompt
+    Ok(prompt.strip_prefix(bos_token).unwrap_or(&prompt).to_string())
+}
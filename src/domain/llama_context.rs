@@ -1,94 +1,304 @@
-use crate::domain::LTokenSequence;
-use crate::{LContext, LContextConfig, LError, LSampleParams, LToken};
+use crate::domain::llama_logits::log_sum_exp;
+use crate::domain::{LCandidates, LGrammarState, LPenalties, LSampler, LSamplerChain, LTemperature, LTokenSequence};
+use crate::{
+    LContext, LContextConfig, LError, LGrammar, LLogitBias, LLogits, LModel, LSampleMode, LSampleParams, LSampleResult, LSessionState, LTimings,
+    LToken, LTokenizeOptions,
+};
 use llama_cpp_sys::{
-    llama_backend_free, llama_context, llama_free, llama_free_model, llama_get_kv_cache_token_count, llama_get_logits, llama_load_model_from_file,
-    llama_n_ctx, llama_n_vocab, llama_new_context_with_model, llama_sample_repetition_penalty, llama_sample_tail_free, llama_sample_temperature,
-    llama_sample_token, llama_sample_top_k, llama_sample_top_p, llama_sample_typical, llama_token_data, llama_token_data_array, llama_tokenize,
+    llama_context, llama_copy_state_data, llama_free, llama_get_logits, llama_get_state_size, llama_get_timings, llama_grammar_accept_token,
+    llama_load_session_file, llama_n_ctx, llama_n_vocab, llama_new_context_with_model, llama_reset_timings, llama_sample_grammar, llama_sample_token,
+    llama_sample_token_mirostat, llama_sample_token_mirostat_v2, llama_save_session_file, llama_set_state_data, llama_token, llama_token_bos,
+    llama_token_eos, llama_tokenize, llama_vocab_type, llama_vocab_type_LLAMA_VOCAB_TYPE_SPM,
 };
+use std::cell::OnceCell;
+use std::cmp::Reverse;
 use std::ffi::CString;
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
+use std::slice;
 
 impl LContext {
+    /// Load the model from the config path and create a single context for it.
+    /// To serve many sessions from one set of weights, load an `LModel` and use `with_model` instead.
     pub fn new(mut config: LContextConfig) -> Result<LContext, LError> {
-        let model_path = config.model_path.to_string_lossy();
-        let model_path_c = CString::new(model_path.as_ref())?;
+        let model = LModel::load(&mut config)?;
+        LContext::with_model(&model, config)
+    }
+
+    /// Create a new context with its own KV cache for an already loaded model.
+    /// The model path and model level settings in config are ignored.
+    pub fn with_model(model: &LModel, mut config: LContextConfig) -> Result<LContext, LError> {
         let context = unsafe {
             let params = config.native_ptr();
-            let model = llama_load_model_from_file(model_path_c.as_ptr(), params);
-            let ctx = llama_new_context_with_model(model, params);
+            let ctx = llama_new_context_with_model(model.native_ptr(), params);
+            if ctx.is_null() {
+                return Err(LError::ContextAllocationFailed(format!(
+                    "failed to create a context with n_ctx {} for model {:?}",
+                    config.n_ctx,
+                    model.path()
+                )));
+            }
             LContext {
-                model,
+                model: model.clone(),
                 ctx,
                 steps: 0,
-                candidates: Vec::new(),
+                logits_all: config.logits_all,
+                embedding: config.embedding,
+                batch_len: 0,
+                n_batch: config.n_batch.max(1) as usize,
+                evaluated: LTokenSequence::new(),
+                candidates: LCandidates::new(),
                 token_history: Vec::new(),
-                token_buffer: vec![0; 2048],
+                mirostat_mu: None,
+                grammar: None,
+                logit_bias: None,
+                penalty_exempt: Vec::new(),
+                special_tokens: OnceCell::new(),
+                vocab_texts: OnceCell::new(),
+                vocab_pieces: OnceCell::new(),
             }
         };
         Ok(context)
     }
 
-    /// Convert a string into a token sequence object.
+    /// Convert a string into a token sequence object, starting with BOS.
     pub fn tokenize(&self, value: &str) -> Result<LTokenSequence, LError> {
-        let mut tokens = LTokenSequence::new();
+        self.tokenize_with(value, Default::default())
+    }
 
-        // We need to allocate enough space for the entire value to fit into the token space.
-        // Since a token can be 1..n in length, we allocate the maximum possible length and
-        // shrink afterwards.
-        tokens.resize(value.bytes().len() + 1);
+    /// Convert a string into a token sequence object, choosing whether BOS and EOS are added and whether
+    /// special tokens written in the text are recognised; see `LTokenizeOptions`.
+    pub fn tokenize_with(&self, value: &str, options: LTokenizeOptions) -> Result<LTokenSequence, LError> {
+        let mut tokens = LTokenSequence::new();
+        if options.add_bos {
+            tokens.push(LToken::from(unsafe { llama_token_bos(self.native_ptr()) }));
+        }
 
-        // Use the context to generate tokens for the input sequence.
-        unsafe {
-            let ctx = self.native_ptr();
-            let value_c = CString::new(value)?;
-            let tokens_buffer_len = tokens.len() as i32;
-            let tokens_buffer_ptr = tokens.native_mut_ptr();
-            let token_count = llama_tokenize(ctx, value_c.as_ptr(), tokens_buffer_ptr, tokens_buffer_len, true);
-            if token_count < 0 {
-                return Err(LError::TokenizationError(format!(
-                    "failed to tokenize string; context returned {} tokens for a string of length {}",
-                    token_count,
-                    value.len()
-                )));
+        if options.parse_special {
+            let special_tokens = self.special_tokens.get_or_init(|| {
+                let vocab = self.vocab();
+                vocab
+                    .special_tokens()
+                    .filter_map(|token| {
+                        let text = vocab.text(&token).filter(|text| !text.is_empty())?;
+                        Some((text, unsafe { token.native_value() }))
+                    })
+                    .collect()
+            });
+            let mut remaining = value;
+            let mut first_fragment = true;
+            loop {
+                // The earliest special token in the text, preferring the longest where several match there
+                let next_special = special_tokens
+                    .iter()
+                    .filter_map(|(text, id)| remaining.find(text.as_str()).map(|start| (start, text, id)))
+                    .min_by_key(|(start, text, _)| (*start, Reverse(text.len())));
+                let fragment = match next_special {
+                    Some((start, _, _)) => &remaining[..start],
+                    None => remaining,
+                };
+                if !fragment.is_empty() {
+                    let fragment_tokens = self.tokenize_text(fragment)?;
+                    if first_fragment {
+                        tokens.extend(&fragment_tokens);
+                    } else {
+                        tokens.extend(&self.strip_leading_space(fragment_tokens));
+                    }
+                    first_fragment = false;
+                }
+                match next_special {
+                    Some((start, text, id)) => {
+                        tokens.push(LToken::from(*id));
+                        remaining = &remaining[start + text.len()..];
+                    }
+                    None => break,
+                }
             }
+        } else {
+            tokens.extend(&self.tokenize_text(value)?);
+        }
 
-            // Shrink buffer to actual token count
-            tokens.resize(token_count as usize);
-        };
+        if options.add_eos {
+            tokens.push(LToken::from(unsafe { llama_token_eos(self.native_ptr()) }));
+        }
+        Ok(tokens)
+    }
 
+    /// Tokenize plain text, without BOS.
+    fn tokenize_text(&self, value: &str) -> Result<LTokenSequence, LError> {
+        let mut tokens = LTokenSequence::new();
+        if value.is_empty() {
+            return Ok(tokens);
+        }
+        let value_c = CString::new(value)?;
+
+        // Tokens are usually several bytes long, so this is normally enough space; if it is not, llama.cpp
+        // returns the negative of the number of tokens needed, and we try again with exactly that many.
+        tokens.resize(value.len() / 2 + 2);
+        let mut token_count = self.tokenize_into(&value_c, &mut tokens);
+        if token_count < 0 {
+            tokens.resize(token_count.unsigned_abs() as usize);
+            token_count = self.tokenize_into(&value_c, &mut tokens);
+        }
+        if token_count < 0 {
+            return Err(LError::TokenizationError(format!(
+                "failed to tokenize string; context returned {} tokens for a string of length {}",
+                token_count,
+                value.len()
+            )));
+        }
+        tokens.resize(token_count as usize);
         Ok(tokens)
     }
 
-    /// Load a sequence of tokens into the context
+    /// SentencePiece tokenizing adds a space to the start of the text, which belongs only at the start of a
+    /// prompt; text between special tokens continues without one. Remove it from the first token, re-matching
+    /// the rest of that token's piece against the vocabulary if the space was merged into it.
+    fn strip_leading_space(&self, tokens: LTokenSequence) -> LTokenSequence {
+        let is_spm = unsafe { llama_vocab_type(self.native_ptr()) == llama_vocab_type_LLAMA_VOCAB_TYPE_SPM };
+        let first = tokens.iter().next();
+        let first = match first {
+            Some(first) if is_spm => first,
+            _ => return tokens,
+        };
+        let piece = match first.as_bytes(self) {
+            Ok(piece) if piece.first() == Some(&b' ') => piece,
+            _ => return tokens,
+        };
+
+        // Match the rest of the piece greedily, longest prefix first
+        let vocab = self.vocab();
+        let mut stripped = LTokenSequence::new();
+        let mut rest = &piece[1..];
+        while !rest.is_empty() {
+            let matched = (1..=rest.len())
+                .rev()
+                .find_map(|length| vocab.find_piece(&rest[..length]).map(|token| (length, token)));
+            match matched {
+                Some((length, token)) => {
+                    stripped.push(token);
+                    rest = &rest[length..];
+                }
+                None => return tokens,
+            }
+        }
+        stripped.extend(&tokens.suffix(1));
+        stripped
+    }
+
+    fn tokenize_into(&self, value: &CString, tokens: &mut LTokenSequence) -> i32 {
+        unsafe { llama_tokenize(self.native_ptr(), value.as_ptr(), tokens.native_mut_ptr(), tokens.len() as i32, false) }
+    }
+
+    /// Convert a token sequence back into text. Tokens without text, like BOS and EOS, are skipped, and
+    /// bytes that are not valid UTF-8 are replaced with U+FFFD.
+    /// SentencePiece vocabularies store a word boundary as a leading space on the piece, and tokenizing adds
+    /// one at the start of the text; like llama.cpp, that space is removed again when the sequence starts with BOS.
+    pub fn detokenize(&self, tokens: &LTokenSequence) -> Result<String, LError> {
+        let mut bytes = Vec::new();
+        let mut tokens_iter = tokens.iter().peekable();
+        let starts_with_bos = tokens_iter.peek().map(|token| token.is_beginning_of_stream(self)).unwrap_or(false);
+        for token in tokens_iter {
+            if token.has_str_value(self) {
+                bytes.extend(token.as_bytes(self)?);
+            }
+        }
+
+        let is_spm = unsafe { llama_vocab_type(self.native_ptr()) == llama_vocab_type_LLAMA_VOCAB_TYPE_SPM };
+        if starts_with_bos && is_spm && bytes.first() == Some(&b' ') {
+            bytes.remove(0);
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Load a sequence of tokens into the context, discarding anything previously evaluated.
     pub fn load_prompt(&mut self, prompt: &LTokenSequence, num_threads: usize) -> Result<(), LError> {
         self.steps = 0;
+        self.reset_mirostat();
+        self.reset_grammar();
+        self.truncate(0);
         self.step(prompt, num_threads)
     }
 
+    /// The tokens currently held in the KV cache, in the order they were evaluated.
+    pub fn evaluated(&self) -> &LTokenSequence {
+        &self.evaluated
+    }
+
+    /// Discard everything after the first length evaluated tokens, so the next step continues from there.
+    /// The logits of the discarded tokens are gone, so you must step again before sampling.
+    pub fn truncate(&mut self, length: usize) {
+        if length < self.evaluated.len() {
+            self.evaluated.resize(length);
+            self.steps = 0;
+        }
+    }
+
     /// Step the model, generating a single new token given the new input tokens from input.
+    /// Input longer than `LContextConfig::n_batch` is evaluated in several batches.
     pub fn step(&mut self, input: &LTokenSequence, num_threads: usize) -> Result<(), LError> {
-        let eval_result = unsafe {
-            let existing_token_count = llama_get_kv_cache_token_count(self.native_ptr());
-            let input_tokens = input.native_ptr();
-            let input_token_count = input.len();
-            let max_length = llama_n_ctx(self.native_ptr());
-            if max_length <= existing_token_count + (input_token_count as i32) {
-                return Err(LError::OutOfBufferSpace(format!(
-                    "You've requested {} additional tokens to a context that is already {} in size with a max size of {}",
-                    input_token_count, existing_token_count, max_length
-                )));
+        self.step_batches(input, num_threads, |_, _| Ok(()))
+    }
+
+    /// Like `step`, but call on_batch after each batch with the range of positions it evaluated, while the
+    /// logits of that batch are still available.
+    pub(crate) fn step_batches(
+        &mut self,
+        input: &LTokenSequence,
+        num_threads: usize,
+        mut on_batch: impl FnMut(&LContext, Range<usize>) -> Result<(), LError>,
+    ) -> Result<(), LError> {
+        let existing_token_count = self.evaluated.len();
+        let max_length = unsafe { llama_n_ctx(self.native_ptr()) } as usize;
+        if max_length <= existing_token_count + input.len() {
+            return Err(LError::OutOfBufferSpace(format!(
+                "You've requested {} additional tokens to a context that is already {} in size with a max size of {}",
+                input.len(),
+                existing_token_count,
+                max_length
+            )));
+        }
+
+        let mut offset = 0;
+        while offset < input.len() {
+            let end = (offset + self.n_batch).min(input.len());
+            let mut batch = input.suffix(offset);
+            batch.resize(end - offset);
+            self.eval_batch(&batch, num_threads)?;
+
+            // The step counts once its first batch is evaluated, so on_batch can read the logits
+            if offset == 0 {
+                self.steps += 1;
             }
+            on_batch(self, existing_token_count + offset..existing_token_count + end)?;
+            offset = end;
+        }
+        Ok(())
+    }
+
+    /// The most tokens that can be evaluated into an empty context, since a step must leave room for at least
+    /// one more token; longer text has to be split into chunks of this size.
+    pub(crate) fn max_chunk_len(&self) -> usize {
+        self.n_ctx().saturating_sub(1)
+    }
+
+    /// Evaluate at most n_batch tokens with a single call to llama.cpp.
+    fn eval_batch(&mut self, batch: &LTokenSequence, num_threads: usize) -> Result<(), LError> {
+        let eval_result = unsafe {
             llama_cpp_sys::llama_eval(
                 self.native_ptr(),
-                input_tokens,
-                input_token_count as i32,
-                existing_token_count,
+                batch.native_ptr(),
+                batch.len() as i32,
+                self.evaluated.len() as i32,
                 num_threads as i32,
             )
         };
         if eval_result != 0i32 {
             return Err(LError::ApiError(format!("eval returned error code {}", eval_result)));
         }
-        self.steps += 1;
+        self.evaluated.extend(batch);
+        self.batch_len = batch.len();
         Ok(())
     }
 
@@ -97,66 +307,388 @@ impl LContext {
             return Err(LError::CannotSampleBeforeInference);
         }
         let active_params = params.unwrap_or(Default::default());
-        let id = unsafe {
-            let logits = llama_get_logits(self.ctx);
-            let n_vocab = llama_n_vocab(self.ctx);
-
-            self.candidates.clear();
-            for token_id in 0..n_vocab {
-                self.candidates.push(llama_token_data {
-                    id: token_id,
-                    logit: *logits.offset(token_id as isize),
-                    p: 0f32,
-                });
+        let mut candidates = self.prepare_candidates()?;
+        let id = self.sample_candidates(&mut candidates, active_params);
+        self.candidates = candidates;
+        let id = id?;
+        self.accept_token(id);
+        Ok(LToken::from(id))
+    }
+
+    /// Sample the next token using chain instead of `LSampleParams`.
+    /// The logit bias and grammar set on the context are applied first; then the samplers in the chain
+    /// run in order, and the token is chosen at random from the remaining candidates.
+    pub fn sample_with_chain(&mut self, chain: &mut LSamplerChain) -> Result<LToken, LError> {
+        if self.steps == 0 {
+            return Err(LError::CannotSampleBeforeInference);
+        }
+        let mut candidates = self.prepare_candidates()?;
+        let id = chain
+            .apply(self, &mut candidates)
+            .and_then(|_| check_candidates(&candidates))
+            .map(|_| self.select(&mut candidates));
+        self.candidates = candidates;
+        let id = id?;
+        self.accept_token(id);
+        Ok(LToken::from(id))
+    }
+
+    /// Candidates for every token from the logits of the last step, with the logit bias and grammar applied.
+    /// The candidates buffer is taken from the context so samplers can borrow the context; put it back when done.
+    fn prepare_candidates(&mut self) -> Result<LCandidates, LError> {
+        let mut candidates = mem::take(&mut self.candidates);
+        unsafe {
+            candidates.fill(self.logits()?);
+            if let Some(logit_bias) = self.logit_bias.as_ref() {
+                // A grammar only allows EOS once it is complete, and then allows nothing else
+                let eos = Some(llama_token_eos(self.ctx)).filter(|_| self.grammar.is_none());
+                logit_bias.apply_to(eos, &mut candidates);
+            }
+            if let Some(grammar) = self.grammar.as_ref() {
+                candidates.with_native(|array| llama_sample_grammar(self.ctx, array, grammar.state));
+            }
+        }
+        check_candidates(&candidates)?;
+        Ok(candidates)
+    }
+
+    fn sample_candidates(&mut self, candidates: &mut LCandidates, params: LSampleParams) -> Result<llama_token, LError> {
+        match params.mode {
+            LSampleMode::Standard => {
+                LSamplerChain::from_params(&params).apply(self, candidates)?;
+                Ok(self.select(candidates))
+            }
+            LSampleMode::Mirostat { tau, eta, m } => {
+                LPenalties::from(&params).apply(self, candidates)?;
+                LTemperature(params.temp).apply(self, candidates)?;
+                if params.temp <= 0f32 {
+                    return Ok(self.select(candidates));
+                }
+                let mu = self.mirostat_mu.get_or_insert(2f32 * tau);
+                Ok(unsafe { candidates.with_native(|array| llama_sample_token_mirostat(self.ctx, array, tau, eta, m, mu)) })
+            }
+            LSampleMode::MirostatV2 { tau, eta } => {
+                LPenalties::from(&params).apply(self, candidates)?;
+                LTemperature(params.temp).apply(self, candidates)?;
+                if params.temp <= 0f32 {
+                    return Ok(self.select(candidates));
+                }
+                let mu = self.mirostat_mu.get_or_insert(2f32 * tau);
+                Ok(unsafe { candidates.with_native(|array| llama_sample_token_mirostat_v2(self.ctx, array, tau, eta, mu)) })
+            }
+        }
+    }
+
+    /// Choose a token at random from the candidates, weighted by probability.
+    fn select(&self, candidates: &mut LCandidates) -> llama_token {
+        unsafe { candidates.with_native(|array| llama_sample_token(self.ctx, array)) }
+    }
+
+    /// Advance the grammar past the sampled token and remember it for the penalties.
+    fn accept_token(&mut self, id: llama_token) {
+        // There is nothing after end of stream.
+        if let Some(grammar) = self.grammar.as_ref() {
+            unsafe {
+                if id != llama_token_eos(self.native_ptr()) {
+                    llama_grammar_accept_token(self.native_ptr(), grammar.state, id);
+                }
             }
+        }
+
+        // No penalty can look further back than the context.
+        self.token_history.push(id);
+        if self.token_history.len() > self.n_ctx() {
+            self.token_history.remove(0);
+        }
+    }
+
+    /// Only sample tokens that continue a match of grammar, or remove the grammar with None.
+    /// The grammar starts matching from its root rule with the next sampled token.
+    pub fn set_grammar(&mut self, grammar: Option<&LGrammar>) {
+        self.grammar = grammar.map(LGrammarState::new);
+    }
+
+    /// Add bias to the logits of the model before every sample, or remove it with None.
+    /// The bias is applied before the repetition penalty and the rest of the sampling chain.
+    pub fn set_logit_bias(&mut self, logit_bias: Option<LLogitBias>) {
+        self.logit_bias = logit_bias.filter(|logit_bias| !logit_bias.is_empty());
+    }
+
+    /// Exempt tokens from the repetition, frequency and presence penalties; eg. punctuation or formatting
+    /// tokens that are expected to repeat. See also `LSampleParams::penalize_newline`.
+    pub fn set_penalty_exempt_tokens(&mut self, tokens: &LTokenSequence) {
+        self.penalty_exempt = tokens.iter().map(|token| unsafe { token.native_value() }).collect();
+    }
+
+    /// Start matching the grammar from its root rule again. This happens automatically in `load_prompt`.
+    pub fn reset_grammar(&mut self) {
+        self.grammar = self.grammar.as_ref().map(LGrammarState::restart);
+    }
+
+    /// Like `sample`, but also return the log-probability of the sampled token and the top_n most likely tokens.
+    pub fn sample_with_logprobs(&mut self, params: Option<LSampleParams>, top_n: usize) -> Result<LSampleResult, LError> {
+        let token = self.sample(params)?;
+        self.logprobs(&token, top_n)
+    }
+
+    /// The log-probability of token and the top_n most likely tokens under the model's output distribution
+    /// from the last step, before any sampling adjustments like temperature or repetition penalties are applied.
+    pub fn logprobs(&self, token: &LToken, top_n: usize) -> Result<LSampleResult, LError> {
+        let logits = self.logits()?;
+        let log_sum = log_sum_exp(logits);
+        let logprob = |logit: f32| logit - log_sum;
+
+        let mut top_logprobs = Vec::new();
+        if top_n > 0 {
+            let mut candidates = LCandidates::from_logits(logits);
+            let top_n = top_n.min(candidates.len());
+            candidates.data.select_nth_unstable_by(top_n - 1, |a, b| b.logit.total_cmp(&a.logit));
+            candidates.truncate(top_n);
+            candidates.sort();
+            top_logprobs = candidates.iter().map(|candidate| (candidate.token(), logprob(candidate.logit))).collect();
+        }
+
+        let logit = logits
+            .get(unsafe { token.native_value() } as usize)
+            .ok_or_else(|| LError::TokenizationError(format!("{:?} is not in the vocabulary", token)))?;
+        Ok(LSampleResult {
+            token: token.clone(),
+            logprob: logprob(*logit),
+            top_logprobs,
+        })
+    }
+
+    /// The logits of the last token evaluated by the last step, indexed by token id.
+    pub fn logits(&self) -> Result<&[f32], LError> {
+        if self.steps == 0 || self.batch_len == 0 {
+            return Err(LError::CannotSampleBeforeInference);
+        }
+        if self.logits_all {
+            self.logits_for(self.evaluated.len() - 1)
+        } else {
+            Ok(unsafe { slice::from_raw_parts(llama_get_logits(self.ctx), self.n_vocab()) })
+        }
+    }
+
+    /// The logits of the token at position in the context, indexed by token id.
+    /// Only positions evaluated by the last batch are available, and only if `LContextConfig::logits_all` is set.
+    pub fn logits_for(&self, position: usize) -> Result<&[f32], LError> {
+        self.all_logits()?.get(position).ok_or_else(|| {
+            LError::LogitsUnavailable(format!(
+                "position {} was not evaluated by the last batch, which covered positions {} to {}",
+                position,
+                self.evaluated.len() - self.batch_len,
+                self.evaluated.len()
+            ))
+        })
+    }
+
+    /// The logits of every token evaluated by the last batch; requires `LContextConfig::logits_all`.
+    pub fn all_logits(&self) -> Result<LLogits<'_>, LError> {
+        if !self.logits_all {
+            return Err(LError::LogitsUnavailable(
+                "the logits of every token are only kept when logits_all is set in the context config".to_string(),
+            ));
+        }
+        if self.steps == 0 || self.batch_len == 0 {
+            return Err(LError::CannotSampleBeforeInference);
+        }
+        let n_vocab = self.n_vocab();
+        let data = unsafe { slice::from_raw_parts(llama_get_logits(self.ctx), n_vocab * self.batch_len) };
+        Ok(LLogits::new(data, n_vocab, self.evaluated.len() - self.batch_len))
+    }
+
+    /// The mirostat running estimate of the maximum surprise, or None until a mirostat sample is taken.
+    pub fn mirostat_mu(&self) -> Option<f32> {
+        self.mirostat_mu
+    }
+
+    /// Forget the mirostat state, so the next mirostat sample starts again from the initial estimate.
+    /// This happens automatically in `load_prompt`.
+    pub fn reset_mirostat(&mut self) {
+        self.mirostat_mu = None;
+    }
+
+    /// Save the evaluated state of this context to a session file.
+    /// `tokens` should be the tokens that have been evaluated so far; they are returned by `load_session`.
+    pub fn save_session<T: AsRef<Path>>(&self, path: T, tokens: &LTokenSequence) -> Result<(), LError> {
+        let path_c = CString::new(path.as_ref().to_string_lossy().as_ref())?;
+        let saved = unsafe { llama_save_session_file(self.native_ptr(), path_c.as_ptr(), tokens.native_ptr(), tokens.len()) };
+        if !saved {
+            return Err(LError::SessionError(format!("failed to save session file {:?}", path.as_ref())));
+        }
+        Ok(())
+    }
+
+    /// Restore the state of this context from a session file created by `save_session`, returning the
+    /// tokens that were evaluated when it was saved. The session must have been saved from the same model.
+    /// With `logits_all` set, the last token is evaluated again using num_threads, so only its logits are available afterwards.
+    pub fn load_session<T: AsRef<Path>>(&mut self, path: T, num_threads: usize) -> Result<LTokenSequence, LError> {
+        if !path.as_ref().is_file() {
+            return Err(LError::SessionError(format!("no session file found at {:?}", path.as_ref())));
+        }
+        let path_c = CString::new(path.as_ref().to_string_lossy().as_ref())?;
 
-            let mut candidates_p = llama_token_data_array {
-                data: self.candidates.as_mut_ptr(),
-                size: self.candidates.len(),
-                sorted: false,
-            };
-
-            let token_history_max_length = active_params.repeat_history_length;
-            let repeat_penalty_scan_length = token_history_max_length.min(self.token_history.len());
-            llama_sample_repetition_penalty(
-                self.ctx,
-                &mut candidates_p,
-                self.token_history.as_ptr(),
-                repeat_penalty_scan_length,
-                active_params.repeat_penalty,
+        let mut tokens = LTokenSequence::new();
+        let loaded = unsafe {
+            tokens.resize(llama_n_ctx(self.native_ptr()) as usize);
+            let mut token_count: usize = 0;
+            let loaded = llama_load_session_file(
+                self.native_ptr(),
+                path_c.as_ptr(),
+                tokens.native_mut_ptr(),
+                tokens.len(),
+                &mut token_count,
             );
+            tokens.resize(token_count);
+            loaded
+        };
+
+        // llama.cpp validates the file version and state size, but only reports the reason on stderr.
+        if !loaded {
+            return Err(LError::SessionMismatch(format!(
+                "failed to load session file {:?}; it may have been saved from a different model or context size",
+                path.as_ref()
+            )));
+        }
+
+        self.steps = if tokens.is_empty() { 0 } else { 1 };
+        self.batch_len = 1;
+        self.evaluated = tokens.clone();
+        self.token_history.clear();
+        self.reset_mirostat();
+
+        // The session file does not record how many tokens the restored logits cover, so with logits_all
+        // the last token is evaluated again to find its logits.
+        if self.logits_all && !tokens.is_empty() {
+            let last = tokens.suffix(tokens.len() - 1);
+            self.truncate(tokens.len() - 1);
+            self.step(&last, num_threads)?;
+        }
+        Ok(tokens)
+    }
 
-            let ctx = self.native_ptr();
-            llama_sample_top_k(ctx, &mut candidates_p, active_params.top_k, 0);
-            llama_sample_tail_free(ctx, &mut candidates_p, active_params.tfs_z, 0);
-            llama_sample_typical(ctx, &mut candidates_p, active_params.typical_p, 0);
-            llama_sample_top_p(ctx, &mut candidates_p, active_params.top_p, 0);
-            llama_sample_temperature(ctx, &mut candidates_p, active_params.temp);
-            llama_sample_token(ctx, &mut candidates_p)
+    /// Take an in-memory copy of the evaluated state of this context.
+    pub fn snapshot(&self) -> LSessionState {
+        let data = unsafe {
+            let mut data = vec![0u8; llama_get_state_size(self.native_ptr())];
+            let written = llama_copy_state_data(self.native_ptr(), data.as_mut_ptr());
+            data.truncate(written);
+            data
         };
+        LSessionState {
+            data,
+            model_path: self.model.path().to_path_buf(),
+            n_ctx: unsafe { llama_n_ctx(self.native_ptr()) },
+            n_vocab: unsafe { llama_n_vocab(self.native_ptr()) },
+            steps: self.steps,
+            batch_len: self.batch_len,
+            evaluated: self.evaluated.clone(),
+            token_history: self.token_history.clone(),
+            mirostat_mu: self.mirostat_mu,
+        }
+    }
 
-        self.update_token_history(id, active_params);
-        Ok(LToken::from(id))
+    /// Restore a state captured by `snapshot`, from this context or another context with the same model and n_ctx.
+    pub fn restore(&mut self, state: &LSessionState) -> Result<(), LError> {
+        let (n_ctx, n_vocab, max_size) = unsafe {
+            (
+                llama_n_ctx(self.native_ptr()),
+                llama_n_vocab(self.native_ptr()),
+                llama_get_state_size(self.native_ptr()),
+            )
+        };
+        if state.model_path != self.model.path() || state.n_vocab != n_vocab {
+            return Err(LError::SessionMismatch(format!(
+                "state was captured from model {:?}, but this context uses {:?}",
+                state.model_path,
+                self.model.path()
+            )));
+        }
+        if state.n_ctx != n_ctx {
+            return Err(LError::SessionMismatch(format!(
+                "state was captured from a context with n_ctx {}, but this context has n_ctx {}",
+                state.n_ctx, n_ctx
+            )));
+        }
+
+        // llama.cpp asserts rather than failing if the state is too large, so check first.
+        if state.data.len() > max_size {
+            return Err(LError::SessionMismatch(format!(
+                "state is {} bytes, which is larger than the maximum state size {} of this context",
+                state.data.len(),
+                max_size
+            )));
+        }
+
+        let mut data = state.data.clone();
+        unsafe {
+            llama_set_state_data(self.native_ptr(), data.as_mut_ptr());
+        }
+        self.steps = state.steps;
+        self.batch_len = state.batch_len;
+        self.evaluated = state.evaluated.clone();
+        self.token_history = state.token_history.clone();
+        self.mirostat_mu = state.mirostat_mu;
+        Ok(())
     }
 
-    fn update_token_history(&mut self, id: llama_cpp_sys::llama_token, params: LSampleParams) {
-        self.token_history.push(id);
-        if self.token_history.len() > params.repeat_history_length {
-            self.token_history.remove(0);
+    /// The number of tokens in the vocabulary of the model
+    pub fn n_vocab(&self) -> usize {
+        unsafe { llama_n_vocab(self.native_ptr()) as usize }
+    }
+
+    /// The maximum number of tokens this context can hold
+    pub fn n_ctx(&self) -> usize {
+        unsafe { llama_n_ctx(self.native_ptr()) as usize }
+    }
+
+    /// Performance counters since the context was created, or since `reset_timings` was called.
+    pub fn timings(&self) -> LTimings {
+        let timings = unsafe { llama_get_timings(self.native_ptr()) };
+        LTimings {
+            load_ms: timings.t_load_ms,
+            sample_ms: timings.t_sample_ms,
+            sample_count: timings.n_sample as usize,
+            prompt_eval_ms: timings.t_p_eval_ms,
+            prompt_eval_count: timings.n_p_eval as usize,
+            eval_ms: timings.t_eval_ms,
+            eval_count: timings.n_eval as usize,
         }
     }
 
+    pub fn reset_timings(&mut self) {
+        unsafe { llama_reset_timings(self.native_ptr()) }
+    }
+
+    /// The model this context was created from
+    pub fn model(&self) -> &LModel {
+        &self.model
+    }
+
     pub(crate) unsafe fn native_ptr(&self) -> *mut llama_context {
         self.ctx
     }
 }
 
+// A context may be moved to another thread, but it is not safe to use from two threads at once.
+unsafe impl Send for LContext {}
+
 impl Drop for LContext {
     fn drop(&mut self) {
         unsafe {
             llama_free(self.ctx);
-            llama_free_model(self.model);
-            llama_backend_free();
         }
     }
 }
+
+/// Refuse to sample when every candidate has been ruled out; the choice would be arbitrary, and llama.cpp aborts
+/// the process if the grammar is then asked to accept a token it does not allow.
+fn check_candidates(candidates: &LCandidates) -> Result<(), LError> {
+    if candidates.iter().all(|candidate| candidate.logit == f32::NEG_INFINITY) {
+        return Err(LError::NoValidTokens(
+            "the logit bias and grammar rule out every token; check for banned tokens the grammar needs".to_string(),
+        ));
+    }
+    Ok(())
+}
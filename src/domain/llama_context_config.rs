@@ -1,6 +1,11 @@
+use crate::domain::LLoadProgress;
 use crate::LContextConfig;
 use llama_cpp_sys::{llama_context_default_params, llama_context_params};
+use std::ffi::c_void;
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::path::{Path, PathBuf};
+use std::ptr;
 
 impl LContextConfig {
     pub fn new<T: AsRef<Path>>(path: T) -> LContextConfig {
@@ -8,8 +13,10 @@ impl LContextConfig {
             LContextConfig {
                 model_path: PathBuf::from(path.as_ref()),
                 params: llama_context_default_params(),
+                progress: None,
                 seed: 0,
                 n_ctx: 512,
+                n_batch: 512,
                 n_parts: -1,
                 f16_kv: true,
                 use_mlock: false,
@@ -22,17 +29,66 @@ impl LContextConfig {
         }
     }
 
+    /// Report model loading progress to callback, as a fraction between 0 and 1.
+    /// Return false from the callback to cancel the load; loading the model then fails with `LError::LoadCancelled`.
+    /// Note that the bundled llama.cpp cannot interrupt a load that is in progress, so the remaining
+    /// weights are still read before the model is discarded, but the callback is not invoked again.
+    pub fn set_progress_callback(&mut self, callback: impl FnMut(f32) -> bool + Send + 'static) {
+        self.progress = Some(LLoadProgress {
+            callback: Box::new(callback),
+            cancelled: false,
+        });
+    }
+
+    /// Reset the cancellation state of the progress callback before starting a new load.
+    pub(crate) fn reset_progress(&mut self) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.cancelled = false;
+        }
+    }
+
+    /// True if the progress callback cancelled the last load.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.progress.as_ref().map(|progress| progress.cancelled).unwrap_or(false)
+    }
+
+    /// The returned params refer to the progress state in this config, so they must not outlive it.
     pub(crate) unsafe fn native_ptr(&mut self) -> llama_context_params {
         self.params.seed = self.seed;
         self.params.n_ctx = self.n_ctx;
+        self.params.n_batch = self.n_batch;
         self.params.f16_kv = self.f16_kv;
         self.params.use_mlock = self.use_mlock;
         self.params.vocab_only = self.vocab_only;
         self.params.logits_all = self.logits_all;
         self.params.embedding = self.embedding;
-        self.params.progress_callback = None;
+        match self.progress.as_mut() {
+            Some(progress) => {
+                self.params.progress_callback = Some(progress_callback);
+                self.params.progress_callback_user_data = progress as *mut LLoadProgress as *mut c_void;
+            }
+            None => {
+                self.params.progress_callback = None;
+                self.params.progress_callback_user_data = ptr::null_mut();
+            }
+        }
         self.params.n_gpu_layers = self.n_gpu_layers;
         self.params.low_vram = self.low_vram;
         self.params
     }
 }
+
+/// Bridge from the native progress callback to the rust closure in `LLoadProgress`.
+unsafe extern "C" fn progress_callback(progress: f32, user_data: *mut c_void) {
+    let state = &mut *(user_data as *mut LLoadProgress);
+    if state.cancelled {
+        return;
+    }
+
+    // Never unwind into C; a panicking callback cancels the load instead.
+    let callback = &mut state.callback;
+    let keep_going = panic::catch_unwind(AssertUnwindSafe(|| callback(progress))).unwrap_or(false);
+    if !keep_going {
+        state.cancelled = true;
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{LContext, LDetokenizer, LError, LToken};
+use std::str;
+
+impl LDetokenizer {
+    pub fn new() -> LDetokenizer {
+        Default::default()
+    }
+
+    /// Add the text of token, returning the text that is now complete.
+    /// Tokens with no text, like BOS and EOS, add nothing.
+    pub fn push(&mut self, context: &LContext, token: &LToken) -> Result<String, LError> {
+        if !token.has_str_value(context) {
+            return Ok(String::new());
+        }
+        let bytes = token.as_bytes(context)?;
+        Ok(self.push_bytes(&bytes))
+    }
+
+    /// Add bytes, returning the text that is now complete. An incomplete character at the end is kept until
+    /// the rest of its bytes arrive, and bytes that can never be valid UTF-8 are replaced with U+FFFD.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let mut text = String::new();
+        let mut start = 0;
+        while start < self.pending.len() {
+            match str::from_utf8(&self.pending[start..]) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    start = self.pending.len();
+                }
+                Err(err) => {
+                    let valid_end = start + err.valid_up_to();
+                    text.push_str(&String::from_utf8_lossy(&self.pending[start..valid_end]));
+                    match err.error_len() {
+                        Some(invalid_length) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            start = valid_end + invalid_length;
+                        }
+                        None => {
+                            start = valid_end;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.pending.drain(..start);
+        text
+    }
+
+    /// True if there are bytes waiting for the rest of a character.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Return whatever is left at the end of the stream, with an incomplete character replaced by U+FFFD.
+    pub fn flush(&mut self) -> String {
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        text
+    }
+}
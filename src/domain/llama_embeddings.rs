@@ -0,0 +1,72 @@
+use crate::domain::default_thread_count;
+use crate::{LContext, LEmbedOptions, LError, LToken, LTokenSequence};
+use llama_cpp_sys::{llama_get_embeddings, llama_n_embd, llama_token_bos};
+use std::slice;
+
+impl Default for LEmbedOptions {
+    fn default() -> Self {
+        LEmbedOptions {
+            worker_thread_count: default_thread_count(),
+            normalize: false,
+        }
+    }
+}
+
+impl LContext {
+    /// The embedding of text, which has `n_embd` values. Requires `LContextConfig::embedding`.
+    /// Text too long for the context is split into chunks that are embedded separately, each starting with BOS,
+    /// and the result is the mean of the chunk embeddings weighted by their length.
+    /// This discards anything previously evaluated.
+    pub fn embed(&mut self, text: &str, options: LEmbedOptions) -> Result<Vec<f32>, LError> {
+        if !self.embedding {
+            return Err(LError::EmbeddingsUnavailable(
+                "the context was not created with embedding set in the context config".to_string(),
+            ));
+        }
+        let tokens = self.tokenize(text)?;
+        let n_embd = self.n_embd();
+
+        // Every chunk but the first has BOS added, which leaves no room for text in a context of two tokens
+        let chunk_len = self.max_chunk_len();
+        if chunk_len < 2 {
+            return Err(LError::InvalidInput(format!(
+                "embedding needs a context of at least 3 tokens, but n_ctx is {}",
+                self.n_ctx()
+            )));
+        }
+        let bos = LToken::from(unsafe { llama_token_bos(self.native_ptr()) });
+        let mut pooled = vec![0f32; n_embd];
+        let mut offset = 0;
+        while offset < tokens.len() {
+            let mut chunk = LTokenSequence::new();
+            if offset > 0 {
+                chunk.push(bos.clone());
+            }
+            let mut chunk_text = tokens.suffix(offset);
+            chunk_text.resize((chunk_len - chunk.len()).min(chunk_text.len()));
+            chunk.extend(&chunk_text);
+            offset += chunk_text.len();
+
+            self.truncate(0);
+            self.step(&chunk, options.worker_thread_count)?;
+            let embeddings = unsafe { slice::from_raw_parts(llama_get_embeddings(self.native_ptr()), n_embd) };
+            let weight = chunk_text.len() as f32 / tokens.len() as f32;
+            for (pooled, value) in pooled.iter_mut().zip(embeddings.iter()) {
+                *pooled += value * weight;
+            }
+        }
+
+        if options.normalize {
+            let norm = pooled.iter().map(|value| value * value).sum::<f32>().sqrt();
+            if norm > 0f32 {
+                pooled.iter_mut().for_each(|value| *value /= norm);
+            }
+        }
+        Ok(pooled)
+    }
+
+    /// The number of values in an embedding
+    pub fn n_embd(&self) -> usize {
+        unsafe { llama_n_embd(self.native_ptr()) as usize }
+    }
+}
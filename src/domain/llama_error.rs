@@ -2,6 +2,7 @@ use std::error::Error;
 use std::ffi::NulError;
 use std::fmt;
 use std::fmt::Formatter;
+use std::path::PathBuf;
 use std::str::Utf8Error;
 
 #[derive(Debug, Clone)]
@@ -17,6 +18,54 @@ pub enum LError {
 
     /// If you try to do something that will not fix in the buffer you've allocated.
     OutOfBufferSpace(String),
+
+    /// There is no model file at the given path.
+    ModelFileMissing(PathBuf),
+
+    /// The model file exists, but could not be read; eg. it is a directory or the permissions are wrong.
+    ModelFileUnreadable(PathBuf, String),
+
+    /// The model file is not a GGUF file, which is the only format the bundled llama.cpp can load.
+    UnsupportedModelFormat(PathBuf, String),
+
+    /// The file passed validation, but llama.cpp still failed to load it.
+    ModelLoadFailed(PathBuf),
+
+    /// The progress callback asked for the model load to be cancelled.
+    LoadCancelled(PathBuf),
+
+    /// A GBNF grammar could not be parsed; the message includes the line and column of the problem.
+    GrammarError(String),
+
+    /// A JSON schema uses features that can't be compiled into a grammar, or is invalid.
+    JsonSchemaError(String),
+
+    /// Generated output could not be deserialized from JSON.
+    JsonError(String),
+
+    /// A session file could not be saved or loaded.
+    SessionError(String),
+
+    /// A saved session or state snapshot does not belong to the model or context it is being restored into.
+    SessionMismatch(String),
+
+    /// The logits for a position were not kept; only the last batch is available, and only its last token unless `logits_all` is set.
+    LogitsUnavailable(String),
+
+    /// Embeddings were requested from a context that was not created with `embedding` set.
+    EmbeddingsUnavailable(String),
+
+    /// An argument is unsuitable for the operation; eg. too few tokens to score.
+    InvalidInput(String),
+
+    /// Every token was ruled out before sampling; eg. the logit bias bans all the tokens the grammar allows.
+    NoValidTokens(String),
+
+    /// A conversation could not be formatted with a chat template.
+    ChatTemplateError(String),
+
+    /// llama.cpp failed to allocate a context for the model; usually this means there is not enough memory for n_ctx.
+    ContextAllocationFailed(String),
 }
 
 impl Error for LError {}
@@ -0,0 +1,166 @@
+use crate::domain::llama_model::GGUF_MAGIC;
+use crate::LError;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+// GGUF metadata value types
+const GGUF_TYPE_UINT8: u32 = 0;
+const GGUF_TYPE_INT8: u32 = 1;
+const GGUF_TYPE_UINT16: u32 = 2;
+const GGUF_TYPE_INT16: u32 = 3;
+const GGUF_TYPE_UINT32: u32 = 4;
+const GGUF_TYPE_INT32: u32 = 5;
+const GGUF_TYPE_FLOAT32: u32 = 6;
+const GGUF_TYPE_BOOL: u32 = 7;
+const GGUF_TYPE_STRING: u32 = 8;
+const GGUF_TYPE_ARRAY: u32 = 9;
+const GGUF_TYPE_UINT64: u32 = 10;
+const GGUF_TYPE_INT64: u32 = 11;
+const GGUF_TYPE_FLOAT64: u32 = 12;
+
+/// Read the string value of key from the metadata of a GGUF file, without loading the model.
+/// The bundled llama.cpp has no API for model metadata, so the header is parsed directly.
+pub(crate) fn read_string_metadata(path: &Path, key: &str) -> Result<Option<String>, LError> {
+    let file = File::open(path).map_err(|err| LError::ModelFileUnreadable(path.to_path_buf(), err.to_string()))?;
+    let mut reader = GgufReader {
+        reader: BufReader::new(file),
+        path: path.to_path_buf(),
+        version: 0,
+    };
+    reader.find_string(key)
+}
+
+struct GgufReader<R: Read> {
+    reader: R,
+    path: PathBuf,
+    version: u32,
+}
+
+impl<R: Read> GgufReader<R> {
+    fn find_string(&mut self, key: &str) -> Result<Option<String>, LError> {
+        let mut magic = [0u8; 4];
+        self.read_exact(&mut magic)?;
+        if &magic != GGUF_MAGIC {
+            return Err(self.format_error("missing GGUF header".to_string()));
+        }
+        self.version = self.read_u32()?;
+
+        let _tensor_count = self.read_count()?;
+        let metadata_count = self.read_count()?;
+        for _ in 0..metadata_count {
+            let metadata_key = self.read_string()?;
+            let value_type = self.read_u32()?;
+            if metadata_key != key.as_bytes() {
+                self.skip_value(value_type)?;
+                continue;
+            }
+            if value_type != GGUF_TYPE_STRING {
+                return Err(self.format_error(format!("metadata {} has type {}, not a string", key, value_type)));
+            }
+            let value = self.read_string()?;
+            return String::from_utf8(value)
+                .map(Some)
+                .map_err(|err| self.format_error(format!("metadata {} is not valid UTF-8: {}", key, err)));
+        }
+        Ok(None)
+    }
+
+    fn skip_value(&mut self, value_type: u32) -> Result<(), LError> {
+        match value_type {
+            GGUF_TYPE_STRING => {
+                let length = self.read_count()?;
+                self.skip(length)
+            }
+            GGUF_TYPE_ARRAY => {
+                let item_type = self.read_u32()?;
+                let count = self.read_count()?;
+                match fixed_size(item_type) {
+                    Some(size) => {
+                        let length = size
+                            .checked_mul(count)
+                            .ok_or_else(|| self.format_error(format!("metadata array of {} items is too large", count)))?;
+                        self.skip(length)
+                    }
+                    None => {
+                        for _ in 0..count {
+                            self.skip_value(item_type)?;
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            _ => match fixed_size(value_type) {
+                Some(size) => self.skip(size),
+                None => Err(self.format_error(format!("unknown metadata type {}", value_type))),
+            },
+        }
+    }
+
+    fn skip(&mut self, length: u64) -> Result<(), LError> {
+        let result = io::copy(&mut self.reader.by_ref().take(length), &mut io::sink());
+        let skipped = result.map_err(|err| self.io_error(err))?;
+        if skipped != length {
+            return Err(self.format_error("unexpected end of file in metadata".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Counts and string lengths are 32 bit in version 1 of the format, and 64 bit after that.
+    fn read_count(&mut self) -> Result<u64, LError> {
+        if self.version == 1 {
+            Ok(self.read_u32()? as u64)
+        } else {
+            self.read_u64()
+        }
+    }
+
+    fn read_string(&mut self) -> Result<Vec<u8>, LError> {
+        let length = self.read_count()?;
+        let mut value = Vec::new();
+        let result = self.reader.by_ref().take(length).read_to_end(&mut value);
+        result.map_err(|err| self.io_error(err))?;
+        if value.len() as u64 != length {
+            return Err(self.format_error("unexpected end of file in metadata".to_string()));
+        }
+        Ok(value)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, LError> {
+        let mut value = [0u8; 4];
+        self.read_exact(&mut value)?;
+        Ok(u32::from_le_bytes(value))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, LError> {
+        let mut value = [0u8; 8];
+        self.read_exact(&mut value)?;
+        Ok(u64::from_le_bytes(value))
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), LError> {
+        self.reader.read_exact(buffer).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => self.format_error("unexpected end of file in metadata".to_string()),
+            _ => self.io_error(err),
+        })
+    }
+
+    fn format_error(&self, message: String) -> LError {
+        LError::UnsupportedModelFormat(self.path.clone(), message)
+    }
+
+    fn io_error(&self, err: io::Error) -> LError {
+        LError::ModelFileUnreadable(self.path.clone(), err.to_string())
+    }
+}
+
+/// The size in bytes of values of a fixed size type
+fn fixed_size(value_type: u32) -> Option<u64> {
+    match value_type {
+        GGUF_TYPE_UINT8 | GGUF_TYPE_INT8 | GGUF_TYPE_BOOL => Some(1),
+        GGUF_TYPE_UINT16 | GGUF_TYPE_INT16 => Some(2),
+        GGUF_TYPE_UINT32 | GGUF_TYPE_INT32 | GGUF_TYPE_FLOAT32 => Some(4),
+        GGUF_TYPE_UINT64 | GGUF_TYPE_INT64 | GGUF_TYPE_FLOAT64 => Some(8),
+        _ => None,
+    }
+}
@@ -0,0 +1,444 @@
+use crate::domain::LGrammarState;
+use crate::{LError, LGrammar};
+use llama_cpp_sys::{
+    llama_grammar_element, llama_grammar_free, llama_grammar_init, llama_gretype, llama_gretype_LLAMA_GRETYPE_ALT, llama_gretype_LLAMA_GRETYPE_CHAR,
+    llama_gretype_LLAMA_GRETYPE_CHAR_ALT, llama_gretype_LLAMA_GRETYPE_CHAR_NOT, llama_gretype_LLAMA_GRETYPE_CHAR_RNG_UPPER,
+    llama_gretype_LLAMA_GRETYPE_END, llama_gretype_LLAMA_GRETYPE_RULE_REF,
+};
+use std::collections::HashMap;
+
+impl LGrammar {
+    /// Parse a grammar in the GBNF format used by llama.cpp; see the llama.cpp grammars folder for examples.
+    /// The grammar must define a rule called root, which is where matching starts.
+    pub fn parse(grammar: &str) -> Result<LGrammar, LError> {
+        GrammarParser::new(grammar).parse()
+    }
+
+    /// The number of rules in the grammar, including the rules generated for groups and repetitions.
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Check whether the grammar matches the whole of text, without a model; eg. to test a grammar.
+    /// Text is matched the same way llama.cpp constrains sampling, so left recursive rules are not supported.
+    pub fn matches(&self, text: &str) -> bool {
+        let mut stacks = Vec::new();
+        self.expand_rule(Vec::new(), self.root, &mut stacks);
+        for c in text.chars() {
+            let mut next_stacks = Vec::new();
+            for stack in stacks.iter() {
+                let (rule, pos) = match stack.last() {
+                    Some(top) => *top,
+                    None => continue,
+                };
+                let (matched, end) = self.match_char(rule, pos, c as u32);
+                if matched {
+                    let mut next_stack = stack[..stack.len() - 1].to_vec();
+                    if !self.is_end_of_sequence(rule, end) {
+                        next_stack.push((rule, end));
+                    }
+                    self.advance_stack(next_stack, &mut next_stacks);
+                }
+            }
+            if next_stacks.is_empty() {
+                return false;
+            }
+            stacks = next_stacks;
+        }
+        stacks.iter().any(|stack| stack.is_empty())
+    }
+
+    /// Expand the rule reference at the top of stack into one stack for each alternative of the rule, until
+    /// every stack ends at a character element, or is empty because the grammar is complete.
+    fn advance_stack(&self, mut stack: Vec<(usize, usize)>, out: &mut Vec<Vec<(usize, usize)>>) {
+        let (rule, pos) = match stack.last() {
+            Some(top) => *top,
+            None => {
+                if !out.contains(&stack) {
+                    out.push(stack);
+                }
+                return;
+            }
+        };
+        let element = &self.rules[rule][pos];
+        if element.type_ != llama_gretype_LLAMA_GRETYPE_RULE_REF {
+            if !out.contains(&stack) {
+                out.push(stack);
+            }
+            return;
+        }
+
+        stack.pop();
+        if !self.is_end_of_sequence(rule, pos + 1) {
+            stack.push((rule, pos + 1));
+        }
+        self.expand_rule(stack, element.value as usize, out);
+    }
+
+    /// Advance one copy of stack for each alternative of rule, with the alternative pushed on top;
+    /// empty alternatives leave the stack as it is. This is how `llama_grammar_init` starts from the root rule.
+    fn expand_rule(&self, stack: Vec<(usize, usize)>, rule: usize, out: &mut Vec<Vec<(usize, usize)>>) {
+        let mut alternative = 0;
+        loop {
+            let mut next_stack = stack.clone();
+            if !self.is_end_of_sequence(rule, alternative) {
+                next_stack.push((rule, alternative));
+            }
+            self.advance_stack(next_stack, out);
+
+            // Skip to the start of the next alternative
+            while !self.is_end_of_sequence(rule, alternative) {
+                alternative += 1;
+            }
+            if self.rules[rule][alternative].type_ == llama_gretype_LLAMA_GRETYPE_END {
+                break;
+            }
+            alternative += 1;
+        }
+    }
+
+    /// Match c against the character element at pos, returning whether it matched and the position after the element.
+    fn match_char(&self, rule: usize, pos: usize, c: u32) -> (bool, usize) {
+        let elements = &self.rules[rule];
+        let is_positive = elements[pos].type_ == llama_gretype_LLAMA_GRETYPE_CHAR;
+        let mut pos = pos;
+        let mut found = false;
+        loop {
+            if elements[pos + 1].type_ == llama_gretype_LLAMA_GRETYPE_CHAR_RNG_UPPER {
+                found = found || (elements[pos].value <= c && c <= elements[pos + 1].value);
+                pos += 2;
+            } else {
+                found = found || elements[pos].value == c;
+                pos += 1;
+            }
+            if elements[pos].type_ != llama_gretype_LLAMA_GRETYPE_CHAR_ALT {
+                break;
+            }
+        }
+        (found == is_positive, pos)
+    }
+
+    fn is_end_of_sequence(&self, rule: usize, pos: usize) -> bool {
+        let type_ = self.rules[rule][pos].type_;
+        type_ == llama_gretype_LLAMA_GRETYPE_END || type_ == llama_gretype_LLAMA_GRETYPE_ALT
+    }
+}
+
+impl LGrammarState {
+    pub(crate) fn new(grammar: &LGrammar) -> LGrammarState {
+        // llama_grammar_init copies the rules, so the pointers only need to live for this call.
+        let mut rules: Vec<*const llama_grammar_element> = grammar.rules.iter().map(|rule| rule.as_ptr()).collect();
+        let state = unsafe { llama_grammar_init(rules.as_mut_ptr(), rules.len(), grammar.root) };
+        LGrammarState {
+            grammar: grammar.clone(),
+            state,
+        }
+    }
+
+    /// A new state for the same grammar, starting again from the root rule.
+    pub(crate) fn restart(&self) -> LGrammarState {
+        LGrammarState::new(&self.grammar)
+    }
+}
+
+impl Drop for LGrammarState {
+    fn drop(&mut self) {
+        unsafe {
+            llama_grammar_free(self.state);
+        }
+    }
+}
+
+/// A recursive descent parser for GBNF, producing the same rules as the llama.cpp grammar parser.
+struct GrammarParser {
+    src: Vec<char>,
+    pos: usize,
+    symbol_ids: HashMap<String, u32>,
+    symbol_names: Vec<String>,
+    rules: Vec<Vec<llama_grammar_element>>,
+}
+
+fn element(type_: llama_gretype, value: u32) -> llama_grammar_element {
+    llama_grammar_element { type_, value }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-'
+}
+
+impl GrammarParser {
+    fn new(grammar: &str) -> GrammarParser {
+        GrammarParser {
+            src: grammar.chars().collect(),
+            pos: 0,
+            symbol_ids: HashMap::new(),
+            symbol_names: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+
+    fn parse(mut self) -> Result<LGrammar, LError> {
+        self.skip_space(true);
+        while self.peek().is_some() {
+            self.parse_rule()?;
+        }
+
+        // Every rule that is referenced must be defined
+        for (symbol_id, name) in self.symbol_names.iter().enumerate() {
+            if self.rules.get(symbol_id).map(|rule| rule.is_empty()).unwrap_or(true) {
+                return Err(LError::GrammarError(format!("undefined rule identifier '{}'", name)));
+            }
+        }
+        let root = match self.symbol_ids.get("root") {
+            Some(root) => *root as usize,
+            None => return Err(LError::GrammarError("grammar does not define a root rule".to_string())),
+        };
+        Ok(LGrammar { rules: self.rules, root })
+    }
+
+    fn parse_rule(&mut self) -> Result<(), LError> {
+        let name = self.parse_name()?;
+        self.skip_space(false);
+        let rule_id = self.symbol_id(&name);
+        if !self.consume("::=") {
+            return Err(self.error("expecting ::="));
+        }
+        self.skip_space(true);
+
+        let rule = self.parse_alternates(&name, false)?;
+        self.add_rule(rule_id, rule);
+
+        // A rule ends at the end of the line
+        match self.peek() {
+            Some('\r') => {
+                self.pos += if self.peek_at(1) == Some('\n') { 2 } else { 1 };
+            }
+            Some('\n') => self.pos += 1,
+            Some(_) => return Err(self.error("expecting newline or end")),
+            None => {}
+        }
+        self.skip_space(true);
+        Ok(())
+    }
+
+    fn parse_alternates(&mut self, rule_name: &str, is_nested: bool) -> Result<Vec<llama_grammar_element>, LError> {
+        let mut rule = Vec::new();
+        self.parse_sequence(rule_name, &mut rule, is_nested)?;
+        while self.peek() == Some('|') {
+            rule.push(element(llama_gretype_LLAMA_GRETYPE_ALT, 0));
+            self.pos += 1;
+            self.skip_space(true);
+            self.parse_sequence(rule_name, &mut rule, is_nested)?;
+        }
+        rule.push(element(llama_gretype_LLAMA_GRETYPE_END, 0));
+        Ok(rule)
+    }
+
+    fn parse_sequence(&mut self, rule_name: &str, out: &mut Vec<llama_grammar_element>, is_nested: bool) -> Result<(), LError> {
+        // The start of the most recent symbol, which a following repetition operator applies to
+        let mut last_sym_start = out.len();
+        while let Some(c) = self.peek() {
+            if c == '"' {
+                // Literal string
+                self.pos += 1;
+                last_sym_start = out.len();
+                while self.peek() != Some('"') {
+                    let value = self.parse_char()?;
+                    out.push(element(llama_gretype_LLAMA_GRETYPE_CHAR, value));
+                }
+                self.pos += 1;
+                self.skip_space(is_nested);
+            } else if c == '[' {
+                // Character class, eg. [a-z0-9_] or [^"]
+                self.pos += 1;
+                let start_type = if self.peek() == Some('^') {
+                    self.pos += 1;
+                    llama_gretype_LLAMA_GRETYPE_CHAR_NOT
+                } else {
+                    llama_gretype_LLAMA_GRETYPE_CHAR
+                };
+                last_sym_start = out.len();
+                while self.peek() != Some(']') {
+                    let value = self.parse_char()?;
+                    let type_ = if last_sym_start < out.len() {
+                        llama_gretype_LLAMA_GRETYPE_CHAR_ALT
+                    } else {
+                        start_type
+                    };
+                    out.push(element(type_, value));
+                    if self.peek() == Some('-') && self.peek_at(1).is_some() && self.peek_at(1) != Some(']') {
+                        self.pos += 1;
+                        let upper = self.parse_char()?;
+                        out.push(element(llama_gretype_LLAMA_GRETYPE_CHAR_RNG_UPPER, upper));
+                    }
+                }
+                self.pos += 1;
+                self.skip_space(is_nested);
+            } else if is_word_char(c) {
+                // Rule reference
+                let name = self.parse_name()?;
+                let ref_rule_id = self.symbol_id(&name);
+                self.skip_space(is_nested);
+                last_sym_start = out.len();
+                out.push(element(llama_gretype_LLAMA_GRETYPE_RULE_REF, ref_rule_id));
+            } else if c == '(' {
+                // Grouping; parsed into a synthesized rule
+                self.pos += 1;
+                self.skip_space(true);
+                let sub_rule_id = self.generate_symbol_id(rule_name);
+                let sub_rule = self.parse_alternates(rule_name, true)?;
+                self.add_rule(sub_rule_id, sub_rule);
+                last_sym_start = out.len();
+                out.push(element(llama_gretype_LLAMA_GRETYPE_RULE_REF, sub_rule_id));
+                if self.peek() != Some(')') {
+                    return Err(self.error("expecting ')'"));
+                }
+                self.pos += 1;
+                self.skip_space(is_nested);
+            } else if c == '*' || c == '+' || c == '?' {
+                if last_sym_start == out.len() {
+                    return Err(self.error(&format!("expecting preceding item to {}", c)));
+                }
+
+                // Apply the operator to the last symbol by rewriting it as a synthesized rule:
+                // S* --> S' ::= S S' |
+                // S+ --> S' ::= S S' | S
+                // S? --> S' ::= S |
+                let sub_rule_id = self.generate_symbol_id(rule_name);
+                let last_sym = out[last_sym_start..].to_vec();
+                let mut sub_rule = last_sym.clone();
+                if c == '*' || c == '+' {
+                    sub_rule.push(element(llama_gretype_LLAMA_GRETYPE_RULE_REF, sub_rule_id));
+                }
+                sub_rule.push(element(llama_gretype_LLAMA_GRETYPE_ALT, 0));
+                if c == '+' {
+                    sub_rule.extend(last_sym);
+                }
+                sub_rule.push(element(llama_gretype_LLAMA_GRETYPE_END, 0));
+                self.add_rule(sub_rule_id, sub_rule);
+
+                out.truncate(last_sym_start);
+                out.push(element(llama_gretype_LLAMA_GRETYPE_RULE_REF, sub_rule_id));
+                self.pos += 1;
+                self.skip_space(is_nested);
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_name(&mut self) -> Result<String, LError> {
+        let start = self.pos;
+        while self.peek().map(is_word_char).unwrap_or(false) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.error("expecting name"));
+        }
+        Ok(self.src[start..self.pos].iter().collect())
+    }
+
+    /// Parse a single, possibly escaped, character and return its code point.
+    fn parse_char(&mut self) -> Result<u32, LError> {
+        match self.peek() {
+            Some('\\') => {
+                let escaped = match self.peek_at(1) {
+                    Some('x') => return self.parse_hex(2),
+                    Some('u') => return self.parse_hex(4),
+                    Some('U') => return self.parse_hex(8),
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('n') => '\n',
+                    Some(c @ ('\\' | '"' | '[' | ']')) => c,
+                    Some(_) => return Err(self.error("unknown escape")),
+                    None => return Err(self.error("unexpected end of input")),
+                };
+                self.pos += 2;
+                Ok(escaped as u32)
+            }
+            Some(c) => {
+                self.pos += 1;
+                Ok(c as u32)
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    /// Parse an escape like \x41, skipping the two character prefix.
+    fn parse_hex(&mut self, digits: usize) -> Result<u32, LError> {
+        self.pos += 2;
+        let mut value = 0u32;
+        for _ in 0..digits {
+            match self.peek().and_then(|c| c.to_digit(16)) {
+                Some(digit) => value = (value << 4) + digit,
+                None => return Err(self.error(&format!("expecting {} hex chars", digits))),
+            }
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    /// Skip whitespace and comments, and newlines if newline_ok is set.
+    fn skip_space(&mut self, newline_ok: bool) {
+        while let Some(c) = self.peek() {
+            if c == ' ' || c == '\t' {
+                self.pos += 1;
+            } else if c == '#' {
+                while self.peek().map(|c| c != '\r' && c != '\n').unwrap_or(false) {
+                    self.pos += 1;
+                }
+            } else if newline_ok && (c == '\r' || c == '\n') {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn consume(&mut self, expected: &str) -> bool {
+        let matches = expected.chars().enumerate().all(|(offset, c)| self.peek_at(offset) == Some(c));
+        if matches {
+            self.pos += expected.chars().count();
+        }
+        matches
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.src.get(self.pos + offset).copied()
+    }
+
+    fn symbol_id(&mut self, name: &str) -> u32 {
+        if let Some(symbol_id) = self.symbol_ids.get(name) {
+            return *symbol_id;
+        }
+        let symbol_id = self.symbol_names.len() as u32;
+        self.symbol_ids.insert(name.to_string(), symbol_id);
+        self.symbol_names.push(name.to_string());
+        symbol_id
+    }
+
+    fn generate_symbol_id(&mut self, base_name: &str) -> u32 {
+        let name = format!("{}_{}", base_name, self.symbol_names.len());
+        self.symbol_id(&name)
+    }
+
+    fn add_rule(&mut self, rule_id: u32, rule: Vec<llama_grammar_element>) {
+        let rule_id = rule_id as usize;
+        if self.rules.len() <= rule_id {
+            self.rules.resize(rule_id + 1, Vec::new());
+        }
+        self.rules[rule_id] = rule;
+    }
+
+    fn error(&self, message: &str) -> LError {
+        let consumed = &self.src[..self.pos.min(self.src.len())];
+        let line = consumed.iter().filter(|c| **c == '\n').count() + 1;
+        let column = consumed.iter().rev().take_while(|c| **c != '\n').count() + 1;
+        LError::GrammarError(format!("{} at line {}, column {}", message, line, column))
+    }
+}
@@ -0,0 +1,654 @@
+use crate::{LError, LGrammar, LJsonSchema};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Optional whitespace between JSON tokens; kept to at most one space so the model can't pad forever.
+const SPACE_RULE: &str = r#"" "?"#;
+
+const BOOLEAN_RULE: &str = r#"("true" | "false") space"#;
+const NUMBER_RULE: &str = r#"("-"? ([0-9] | [1-9] [0-9]*)) ("." [0-9]+)? ([eE] [-+]? [0-9]+)? space"#;
+const INTEGER_RULE: &str = r#"("-"? ([0-9] | [1-9] [0-9]*)) space"#;
+const STRING_RULE: &str = r#""\"" ([^"\\\x7F\x00-\x1F] | "\\" (["\\/bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F]))* "\"" space"#;
+const NULL_RULE: &str = r#""null" space"#;
+
+/// Any character that may appear unescaped in a JSON string; control characters, `"` and `\` must be escaped.
+/// DEL is allowed by JSON, but excluded as it is by llama.cpp.
+const STRING_CHAR: &str = r#"[^"\\\x7F\x00-\x1F]"#;
+
+/// The characters of the `\d`, `\w` and `\s` regex shorthands, as sorted ranges; `\s` is the JavaScript set.
+const DIGIT_CLASS: &[(char, char)] = &[('0', '9')];
+const WORD_CLASS: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE_CLASS: &[(char, char)] = &[
+    ('\t', '\r'),
+    (' ', ' '),
+    ('\u{a0}', '\u{a0}'),
+    ('\u{1680}', '\u{1680}'),
+    ('\u{2000}', '\u{200a}'),
+    ('\u{2028}', '\u{2029}'),
+    ('\u{202f}', '\u{202f}'),
+    ('\u{205f}', '\u{205f}'),
+    ('\u{3000}', '\u{3000}'),
+    ('\u{feff}', '\u{feff}'),
+];
+
+impl LJsonSchema {
+    /// Compile the schema into GBNF text that only matches JSON valid for the schema.
+    /// String patterns may use literals, `.`, character classes, the `\d`, `\w` and `\s` shorthands and their
+    /// negations, the `\n`, `\r`, `\t`, `\f`, `\v`, `\0`, `\xHH` and `\uHHHH` escapes and escaped punctuation,
+    /// groups, alternation, and the `*`, `+`, `?` and `{n,m}` quantifiers; a pattern always matches the whole string.
+    /// Other escapes, like the `\b` word boundary, are reported as errors.
+    pub fn to_gbnf(&self) -> Result<String, LError> {
+        let mut compiler = SchemaCompiler::default();
+        compiler.rules.insert("space".to_string(), SPACE_RULE.to_string());
+        let root = compiler.visit(self, "root")?;
+        if root != "root" {
+            compiler.rules.insert("root".to_string(), root);
+        }
+
+        // Emit root first so the grammar reads top down
+        let mut gbnf = String::new();
+        let mut names: Vec<&String> = compiler.rules.keys().filter(|name| *name != "root").collect();
+        names.sort();
+        for name in ["root".to_string()].iter().chain(names) {
+            let _ = writeln!(gbnf, "{} ::= {}", name, compiler.rules[name]);
+        }
+        Ok(gbnf)
+    }
+}
+
+impl LGrammar {
+    /// A grammar that only matches JSON valid for schema.
+    pub fn from_json_schema(schema: &LJsonSchema) -> Result<LGrammar, LError> {
+        LGrammar::parse(&schema.to_gbnf()?)
+    }
+}
+
+#[derive(Default)]
+struct SchemaCompiler {
+    rules: HashMap<String, String>,
+}
+
+impl SchemaCompiler {
+    /// Add a rule for schema, returning the name of the rule.
+    fn visit(&mut self, schema: &LJsonSchema, name: &str) -> Result<String, LError> {
+        let rule = match schema {
+            LJsonSchema::Boolean => return Ok(self.add_primitive("boolean", BOOLEAN_RULE)),
+            LJsonSchema::Number => return Ok(self.add_primitive("number", NUMBER_RULE)),
+            LJsonSchema::Integer => return Ok(self.add_primitive("integer", INTEGER_RULE)),
+            LJsonSchema::Null => return Ok(self.add_primitive("null", NULL_RULE)),
+            LJsonSchema::String { pattern: None } => return Ok(self.add_primitive("string", STRING_RULE)),
+            LJsonSchema::String { pattern: Some(pattern) } => {
+                format!(r#""\"" {} "\"" space"#, PatternCompiler::new(pattern).compile()?)
+            }
+            LJsonSchema::Enum(values) => {
+                if values.is_empty() {
+                    return Err(LError::JsonSchemaError("enum must have at least one value".to_string()));
+                }
+                let alternatives: Vec<String> = values.iter().map(|value| format_literal(value)).collect();
+                format!("({}) space", alternatives.join(" | "))
+            }
+            LJsonSchema::OneOf(schemas) => {
+                if schemas.is_empty() {
+                    return Err(LError::JsonSchemaError("oneOf must have at least one schema".to_string()));
+                }
+                let mut alternatives = Vec::new();
+                for (index, schema) in schemas.iter().enumerate() {
+                    alternatives.push(self.visit(schema, &format!("{}-{}", name, index))?);
+                }
+                alternatives.join(" | ")
+            }
+            LJsonSchema::Array { items } => {
+                let item_rule = self.visit(items, &format!("{}-item", name))?;
+                format!(r#""[" space ({} ("," space {})*)? "]" space"#, item_rule, item_rule)
+            }
+            LJsonSchema::Object { properties, required } => self.visit_object(properties, required, name)?,
+        };
+        Ok(self.add_rule(name, rule))
+    }
+
+    fn visit_object(&mut self, properties: &[(String, LJsonSchema)], required: &[String], name: &str) -> Result<String, LError> {
+        for required_name in required {
+            if !properties.iter().any(|(property_name, _)| property_name == required_name) {
+                return Err(LError::JsonSchemaError(format!("required property '{}' is not defined", required_name)));
+            }
+        }
+
+        // Required properties come first, then optional ones, both in the order they were declared.
+        let mut required_pairs = Vec::new();
+        let mut optional_pairs = Vec::new();
+        for (property_name, property_schema) in properties {
+            let value_rule = self.visit(property_schema, &format!("{}-{}", name, property_name))?;
+            let pair = format!(r#"{} space ":" space {}"#, format_literal(&json_quote(property_name)), value_rule);
+            if required.contains(property_name) {
+                required_pairs.push(pair);
+            } else {
+                optional_pairs.push(pair);
+            }
+        }
+
+        let mut rule = r#""{" space"#.to_string();
+        if !required_pairs.is_empty() {
+            rule.push(' ');
+            rule.push_str(&required_pairs.join(r#" "," space "#));
+            for pair in optional_pairs.iter() {
+                let _ = write!(rule, r#" ("," space {})?"#, pair);
+            }
+        } else if !optional_pairs.is_empty() {
+            // Any subset of the optional properties, in order; each alternative starts with a different property.
+            let alternatives: Vec<String> = (0..optional_pairs.len())
+                .map(|first| {
+                    let mut alternative = optional_pairs[first].clone();
+                    for pair in optional_pairs[first + 1..].iter() {
+                        let _ = write!(alternative, r#" ("," space {})?"#, pair);
+                    }
+                    alternative
+                })
+                .collect();
+            let _ = write!(rule, " ({})?", alternatives.join(" | "));
+        }
+        rule.push_str(r#" "}" space"#);
+        Ok(rule)
+    }
+
+    fn add_primitive(&mut self, name: &str, rule: &str) -> String {
+        self.rules.insert(name.to_string(), rule.to_string());
+        name.to_string()
+    }
+
+    /// Add a rule, choosing a unique valid rule name based on name.
+    fn add_rule(&mut self, name: &str, rule: String) -> String {
+        let base: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' })
+            .collect();
+        let mut unique = base.clone();
+        let mut index = 0;
+        while let Some(existing) = self.rules.get(&unique) {
+            if *existing == rule {
+                return unique;
+            }
+            index += 1;
+            unique = format!("{}{}", base, index);
+        }
+        self.rules.insert(unique.clone(), rule);
+        unique
+    }
+}
+
+/// Quote value as a JSON string.
+fn json_quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        quoted.push_str(&json_escape(c));
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The text that represents c inside a JSON string.
+fn json_escape(c: char) -> String {
+    match c {
+        '"' => "\\\"".to_string(),
+        '\\' => "\\\\".to_string(),
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        c if is_string_unsafe(c) => format!("\\u{:04x}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+/// True for the characters that may not appear unescaped in a JSON string; see `STRING_CHAR`.
+fn is_string_unsafe(c: char) -> bool {
+    c == '"' || c == '\\' || c == '\x7f' || (c as u32) < 0x20
+}
+
+/// Format text as a GBNF string literal matching exactly that text.
+fn format_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        literal.push_str(&escape_char(c));
+    }
+    literal.push('"');
+    literal
+}
+
+/// Escape a single character for use in a GBNF literal or character class.
+fn escape_char(c: char) -> String {
+    match c {
+        '"' => "\\\"".to_string(),
+        '\\' => "\\\\".to_string(),
+        '[' => "\\[".to_string(),
+        ']' => "\\]".to_string(),
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        c if (c as u32) < 0x20 => format!("\\x{:02x}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+#[cfg(feature = "serde")]
+impl LJsonSchema {
+    /// Parse a JSON Schema document.
+    pub fn parse(schema: &str) -> Result<LJsonSchema, LError> {
+        let schema: serde_json::Value = serde_json::from_str(schema).map_err(|err| LError::JsonSchemaError(format!("invalid JSON: {}", err)))?;
+        LJsonSchema::from_value(&schema)
+    }
+
+    /// Convert a JSON Schema value; enum, const, oneOf, anyOf and the basic types are supported.
+    pub fn from_value(schema: &serde_json::Value) -> Result<LJsonSchema, LError> {
+        use serde_json::Value;
+
+        let object = schema
+            .as_object()
+            .ok_or_else(|| LError::JsonSchemaError(format!("expected a schema object, found {}", schema)))?;
+        if let Some(values) = object.get("enum") {
+            let values = values
+                .as_array()
+                .ok_or_else(|| LError::JsonSchemaError("enum must be an array".to_string()))?;
+            return Ok(LJsonSchema::Enum(values.iter().map(|value| value.to_string()).collect()));
+        }
+        if let Some(value) = object.get("const") {
+            return Ok(LJsonSchema::Enum(vec![value.to_string()]));
+        }
+        for key in ["oneOf", "anyOf"] {
+            if let Some(schemas) = object.get(key) {
+                let schemas = schemas
+                    .as_array()
+                    .ok_or_else(|| LError::JsonSchemaError(format!("{} must be an array", key)))?;
+                return Ok(LJsonSchema::OneOf(schemas.iter().map(LJsonSchema::from_value).collect::<Result<_, _>>()?));
+            }
+        }
+
+        match object.get("type") {
+            Some(Value::String(type_name)) => LJsonSchema::from_type(type_name, object),
+            Some(Value::Array(type_names)) => {
+                let mut schemas = Vec::new();
+                for type_name in type_names {
+                    let type_name = type_name
+                        .as_str()
+                        .ok_or_else(|| LError::JsonSchemaError(format!("invalid type {}", type_name)))?;
+                    schemas.push(LJsonSchema::from_type(type_name, object)?);
+                }
+                Ok(LJsonSchema::OneOf(schemas))
+            }
+            None if object.contains_key("properties") => LJsonSchema::from_type("object", object),
+            None if object.contains_key("items") => LJsonSchema::from_type("array", object),
+            _ => Err(LError::JsonSchemaError(format!("unsupported schema {}", schema))),
+        }
+    }
+
+    fn from_type(type_name: &str, object: &serde_json::Map<String, serde_json::Value>) -> Result<LJsonSchema, LError> {
+        match type_name {
+            "object" => {
+                let mut properties = Vec::new();
+                if let Some(property_schemas) = object.get("properties").and_then(|properties| properties.as_object()) {
+                    for (property_name, property_schema) in property_schemas {
+                        properties.push((property_name.clone(), LJsonSchema::from_value(property_schema)?));
+                    }
+                }
+                let required = object
+                    .get("required")
+                    .and_then(|required| required.as_array())
+                    .map(|required| required.iter().filter_map(|name| name.as_str()).map(|name| name.to_string()).collect())
+                    .unwrap_or_default();
+                Ok(LJsonSchema::Object { properties, required })
+            }
+            "array" => match object.get("items") {
+                Some(items) => Ok(LJsonSchema::Array {
+                    items: Box::new(LJsonSchema::from_value(items)?),
+                }),
+                None => Err(LError::JsonSchemaError("array schemas must define items".to_string())),
+            },
+            "string" => Ok(LJsonSchema::String {
+                pattern: object
+                    .get("pattern")
+                    .and_then(|pattern| pattern.as_str())
+                    .map(|pattern| pattern.to_string()),
+            }),
+            "number" => Ok(LJsonSchema::Number),
+            "integer" => Ok(LJsonSchema::Integer),
+            "boolean" => Ok(LJsonSchema::Boolean),
+            "null" => Ok(LJsonSchema::Null),
+            _ => Err(LError::JsonSchemaError(format!("unsupported type '{}'", type_name))),
+        }
+    }
+}
+
+/// Translates a regular expression into a GBNF expression; `^` and `$` anchors are ignored.
+struct PatternCompiler {
+    src: Vec<char>,
+    pos: usize,
+}
+
+impl PatternCompiler {
+    fn new(pattern: &str) -> PatternCompiler {
+        let mut src: Vec<char> = pattern.chars().collect();
+        if src.first() == Some(&'^') {
+            src.remove(0);
+        }
+        // A trailing '$' is an anchor unless it is escaped by an odd number of backslashes
+        if src.last() == Some(&'$') && src.iter().rev().skip(1).take_while(|c| **c == '\\').count() % 2 == 0 {
+            src.pop();
+        }
+        PatternCompiler { src, pos: 0 }
+    }
+
+    fn compile(mut self) -> Result<String, LError> {
+        let alternatives = self.parse_alternatives()?;
+        if self.pos < self.src.len() {
+            return Err(self.error("unbalanced ')'"));
+        }
+        Ok(alternatives)
+    }
+
+    fn parse_alternatives(&mut self) -> Result<String, LError> {
+        let mut alternatives = vec![self.parse_sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.parse_sequence()?);
+        }
+        Ok(format!("({})", alternatives.join(" | ")))
+    }
+
+    fn parse_sequence(&mut self) -> Result<String, LError> {
+        let mut items: Vec<String> = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let item = self.parse_item()?;
+            let item = self.parse_quantifier(item)?;
+            items.push(item);
+        }
+        Ok(items.join(" "))
+    }
+
+    fn parse_item(&mut self) -> Result<String, LError> {
+        let c = self.next().ok_or_else(|| self.error("unexpected end of pattern"))?;
+        match c {
+            '(' => {
+                // Capture groups and non-capturing groups are the same thing here
+                if self.peek() == Some('?') && self.peek_at(1) == Some(':') {
+                    self.pos += 2;
+                }
+                let group = self.parse_alternatives()?;
+                if self.next() != Some(')') {
+                    return Err(self.error("expecting ')'"));
+                }
+                Ok(group)
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(STRING_CHAR.to_string()),
+            '\\' => match self.parse_escape()? {
+                PatternEscape::Char(c) => Ok(format_literal(&json_escape(c))),
+                PatternEscape::Class(ranges, false) => format_class(ranges),
+                PatternEscape::Class(ranges, true) => Ok(format_negated_class(ranges)),
+            },
+            '*' | '+' | '?' | '{' => Err(self.error(&format!("nothing to repeat before '{}'", c))),
+            c => Ok(format_literal(&json_escape(c))),
+        }
+    }
+
+    /// Translate a character class. The characters that must be escaped in a JSON string are matched by their
+    /// escape sequence when the class names them, and never by a negated class.
+    fn parse_class(&mut self) -> Result<String, LError> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        loop {
+            let first = match self.next() {
+                None => return Err(self.error("unterminated character class")),
+                Some(']') => break,
+                Some('\\') => match self.parse_escape()? {
+                    PatternEscape::Char(c) => c,
+                    PatternEscape::Class(shorthand, false) => {
+                        ranges.extend_from_slice(shorthand);
+                        continue;
+                    }
+                    PatternEscape::Class(shorthand, true) => {
+                        ranges.extend(complement(shorthand));
+                        continue;
+                    }
+                },
+                Some(c) => c,
+            };
+            if self.peek() == Some('-') && self.peek_at(1).is_some() && self.peek_at(1) != Some(']') {
+                self.pos += 1;
+                let last = match self.next() {
+                    Some('\\') => match self.parse_escape()? {
+                        PatternEscape::Char(c) => c,
+                        PatternEscape::Class(..) => return Err(self.error("a character class shorthand cannot end a range")),
+                    },
+                    Some(c) => c,
+                    None => return Err(self.error("unterminated character class")),
+                };
+                if last < first {
+                    return Err(self.error("character class range is out of order"));
+                }
+                ranges.push((first, last));
+            } else {
+                ranges.push((first, first));
+            }
+        }
+
+        if negated {
+            return Ok(format_negated_class(&ranges));
+        }
+        format_class(&ranges).map_err(|_| self.error("empty character class"))
+    }
+
+    /// Parse the escape after a backslash: a single character, or a shorthand class like `\d`, or its negation `\D`.
+    fn parse_escape(&mut self) -> Result<PatternEscape, LError> {
+        let c = self.next().ok_or_else(|| self.error("unexpected end of pattern"))?;
+        let escaped = match c {
+            'd' => return Ok(PatternEscape::Class(DIGIT_CLASS, false)),
+            'D' => return Ok(PatternEscape::Class(DIGIT_CLASS, true)),
+            'w' => return Ok(PatternEscape::Class(WORD_CLASS, false)),
+            'W' => return Ok(PatternEscape::Class(WORD_CLASS, true)),
+            's' => return Ok(PatternEscape::Class(SPACE_CLASS, false)),
+            'S' => return Ok(PatternEscape::Class(SPACE_CLASS, true)),
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'f' => '\x0c',
+            'v' => '\x0b',
+            '0' if !self.peek().map(|c| c.is_ascii_digit()).unwrap_or(false) => '\0',
+            'x' => self.parse_hex(2)?,
+            'u' => self.parse_hex(4)?,
+            c if c.is_ascii_punctuation() || c == ' ' => c,
+            c => {
+                self.pos -= 1;
+                return Err(self.error(&format!("unsupported escape '\\{}'", c)));
+            }
+        };
+        Ok(PatternEscape::Char(escaped))
+    }
+
+    /// Parse the hex digits of an escape like \x41.
+    fn parse_hex(&mut self, digits: usize) -> Result<char, LError> {
+        let mut value = 0u32;
+        for _ in 0..digits {
+            match self.next().and_then(|c| c.to_digit(16)) {
+                Some(digit) => value = (value << 4) + digit,
+                None => return Err(self.error(&format!("expecting {} hex digits", digits))),
+            }
+        }
+        char::from_u32(value).ok_or_else(|| self.error("escape is not a valid character"))
+    }
+
+    fn parse_quantifier(&mut self, item: String) -> Result<String, LError> {
+        match self.peek() {
+            Some(c @ ('*' | '+' | '?')) => {
+                self.pos += 1;
+                Ok(format!("({}){}", item, c))
+            }
+            Some('{') => {
+                self.pos += 1;
+                let min = self.parse_number().ok_or_else(|| self.error("expecting a repeat count"))?;
+                let max = if self.peek() == Some(',') {
+                    self.pos += 1;
+                    self.parse_number()
+                } else {
+                    Some(min)
+                };
+                if self.next() != Some('}') {
+                    return Err(self.error("expecting '}'"));
+                }
+                if let Some(max) = max {
+                    if max < min {
+                        return Err(self.error("repeat count maximum is less than the minimum"));
+                    }
+                }
+
+                let mut repeated: Vec<String> = (0..min).map(|_| format!("({})", item)).collect();
+                match max {
+                    // {n,}: any number of further repeats
+                    None => repeated.push(format!("({})*", item)),
+                    // {n,m}: up to m - n further repeats, nested so each one depends on the one before
+                    Some(max) => {
+                        let mut optional = String::new();
+                        for _ in min..max {
+                            optional = if optional.is_empty() {
+                                format!("({})?", item)
+                            } else {
+                                format!("({} {})?", item, optional)
+                            };
+                        }
+                        if !optional.is_empty() {
+                            repeated.push(optional);
+                        }
+                    }
+                }
+                Ok(format!("({})", repeated.join(" ")))
+            }
+            _ => Ok(item),
+        }
+    }
+
+    fn parse_number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().map(|c| c.is_ascii_digit()).unwrap_or(false) {
+            self.pos += 1;
+        }
+        self.src[start..self.pos].iter().collect::<String>().parse().ok()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.src.get(self.pos + offset).copied()
+    }
+
+    fn error(&self, message: &str) -> LError {
+        let pattern: String = self.src.iter().collect();
+        LError::JsonSchemaError(format!("{} at offset {} of pattern '{}'", message, self.pos, pattern))
+    }
+}
+
+/// A backslash escape in a pattern.
+enum PatternEscape {
+    Char(char),
+
+    /// The ranges of a shorthand class like `\d`, and whether it is negated, like `\D`
+    Class(&'static [(char, char)], bool),
+}
+
+/// The ranges of every character that is not in ranges, which must be sorted.
+fn complement(ranges: &[(char, char)]) -> Vec<(char, char)> {
+    let mut complement = Vec::new();
+    let mut start = 0u32;
+    for &(first, last) in ranges {
+        if first as u32 > start {
+            complement.push((char_at(start), char_at(first as u32 - 1)));
+        }
+        start = last as u32 + 1;
+    }
+    if start <= char::MAX as u32 {
+        complement.push((char_at(start), char::MAX));
+    }
+    complement
+}
+
+/// A GBNF class matching any character that is not in ranges, and that can appear unescaped in a JSON string.
+fn format_negated_class(ranges: &[(char, char)]) -> String {
+    let mut class = String::from("[^");
+    for &(first, last) in ranges {
+        class.push_str(&format_class_range(first, last));
+    }
+    class.push_str(r#""\\\x7F\x00-\x1F]"#);
+    class
+}
+
+/// A GBNF expression matching any character in ranges as it appears in a JSON string; characters that must be
+/// escaped are matched by their escape sequence instead.
+fn format_class(ranges: &[(char, char)]) -> Result<String, LError> {
+    let mut class = String::new();
+    let mut escaped = Vec::new();
+    for &(first, last) in ranges {
+        // Split the range around the characters that need escaping
+        let mut start = first as u32;
+        for unsafe_char in (0..0x20).chain([0x22, 0x5c, 0x7f]) {
+            if unsafe_char < start || unsafe_char > last as u32 {
+                continue;
+            }
+            if unsafe_char > start {
+                class.push_str(&format_class_range(char_at(start), char_at(unsafe_char - 1)));
+            }
+            let escape = format_literal(&json_escape(char_at(unsafe_char)));
+            if !escaped.contains(&escape) {
+                escaped.push(escape);
+            }
+            start = unsafe_char + 1;
+        }
+        if start <= last as u32 {
+            class.push_str(&format_class_range(char_at(start), last));
+        }
+    }
+
+    let mut alternatives = escaped;
+    if !class.is_empty() {
+        alternatives.insert(0, format!("[{}]", class));
+    }
+    match alternatives.len() {
+        0 => Err(LError::JsonSchemaError("empty character class".to_string())),
+        1 => Ok(alternatives.remove(0)),
+        _ => Ok(format!("({})", alternatives.join(" | "))),
+    }
+}
+
+/// A range as a member of a GBNF character class.
+fn format_class_range(first: char, last: char) -> String {
+    if first == last {
+        escape_class_char(first)
+    } else {
+        format!("{}-{}", escape_class_char(first), escape_class_char(last))
+    }
+}
+
+/// Escape a character for a GBNF character class, where `-` and a leading `^` have special meaning.
+fn escape_class_char(c: char) -> String {
+    match c {
+        '-' => "\\x2d".to_string(),
+        '^' => "\\x5e".to_string(),
+        c => escape_char(c),
+    }
+}
+
+/// The character with code point value, which is always valid next to one of the JSON unsafe characters.
+fn char_at(value: u32) -> char {
+    char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
@@ -0,0 +1,72 @@
+use crate::domain::LCandidates;
+use crate::{LContext, LError, LLogitBias, LToken};
+use llama_cpp_sys::llama_token;
+
+impl LLogitBias {
+    pub fn new() -> LLogitBias {
+        Default::default()
+    }
+
+    /// Add bias to the logit of token; positive values make it more likely, negative values less likely.
+    /// Setting the bias of a token again replaces the previous value.
+    pub fn set(&mut self, token: &LToken, bias: f32) {
+        self.biases.insert(unsafe { token.native_value() }, bias);
+    }
+
+    /// Never sample token.
+    pub fn ban(&mut self, token: &LToken) {
+        self.set(token, f32::NEG_INFINITY);
+    }
+
+    /// Stop adjusting the logit of token.
+    pub fn remove(&mut self, token: &LToken) {
+        self.biases.remove(&unsafe { token.native_value() });
+    }
+
+    /// The bias applied to token, if any.
+    pub fn get(&self, token: &LToken) -> Option<f32> {
+        self.biases.get(&unsafe { token.native_value() }).copied()
+    }
+
+    /// Add bias to every token that text tokenizes to.
+    /// Text that is more than one token does not bias the sequence as a whole: each of its tokens is biased
+    /// separately, wherever it appears. Banning " Parisian" (" Paris", "ian") also bans "ian" in "Christian";
+    /// check `context.tokenize(text)` when the text may split into common pieces.
+    /// Note that text is tokenized on its own, as if it were the start of a prompt; for llama models that
+    /// means a leading space is added, so "Paris" matches the token the model produces mid sentence.
+    pub fn set_text(&mut self, context: &LContext, text: &str, bias: f32) -> Result<(), LError> {
+        for token in context.tokenize(text)?.iter() {
+            if !token.is_beginning_of_stream(context) {
+                self.set(&token, bias);
+            }
+        }
+        Ok(())
+    }
+
+    /// Never sample any of the tokens that text tokenizes to; for text of more than one token, this bans
+    /// each of its tokens everywhere, not just the sequence; see `set_text`.
+    pub fn ban_text(&mut self, context: &LContext, text: &str) -> Result<(), LError> {
+        self.set_text(context, text, f32::NEG_INFINITY)
+    }
+
+    /// The number of tokens with a bias, not counting `ban_eos`.
+    pub fn len(&self) -> usize {
+        self.biases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.biases.is_empty() && !self.ban_eos
+    }
+
+    /// Add the biases to the logits of candidates; eos is the token banned by `ban_eos`, or None to leave it alone.
+    pub(crate) fn apply_to(&self, eos: Option<llama_token>, candidates: &mut LCandidates) {
+        for candidate in candidates.as_mut_slice() {
+            if let Some(bias) = self.biases.get(&candidate.id) {
+                candidate.logit += bias;
+            }
+            if self.ban_eos && Some(candidate.id) == eos {
+                candidate.logit = f32::NEG_INFINITY;
+            }
+        }
+    }
+}
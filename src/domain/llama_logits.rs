@@ -0,0 +1,49 @@
+use crate::LLogits;
+use std::slice;
+
+impl<'a> LLogits<'a> {
+    pub(crate) fn new(data: &'a [f32], n_vocab: usize, start: usize) -> LLogits<'a> {
+        LLogits { data, n_vocab, start }
+    }
+
+    /// The number of positions with logits
+    pub fn len(&self) -> usize {
+        self.data.len() / self.n_vocab
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The number of logits for each position
+    pub fn n_vocab(&self) -> usize {
+        self.n_vocab
+    }
+
+    /// The position in the context of the first row
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The logits of the token at position in the context, if it was evaluated by the last batch.
+    pub fn get(&self, position: usize) -> Option<&'a [f32]> {
+        let row = position.checked_sub(self.start)?;
+        self.data.get(row * self.n_vocab..(row + 1) * self.n_vocab)
+    }
+
+    /// The logit of token id at position in the context.
+    pub fn logit(&self, position: usize, id: usize) -> Option<f32> {
+        self.get(position)?.get(id).copied()
+    }
+
+    /// The logits of each position in order, starting from `start`.
+    pub fn rows(&self) -> slice::ChunksExact<'a, f32> {
+        self.data.chunks_exact(self.n_vocab)
+    }
+}
+
+/// The log of the softmax denominator of logits, so the log-probability of token id is `logits[id] - log_sum_exp(logits)`.
+pub(crate) fn log_sum_exp(logits: &[f32]) -> f32 {
+    let max_logit = logits.iter().fold(f32::NEG_INFINITY, |max, logit| max.max(*logit));
+    max_logit + logits.iter().map(|logit| (logit - max_logit).exp()).sum::<f32>().ln()
+}
@@ -0,0 +1,116 @@
+use crate::domain::llama_gguf::read_string_metadata;
+use crate::domain::LModelHandle;
+use crate::{LContextConfig, LError, LModel};
+use llama_cpp_sys::{llama_free_model, llama_load_model_from_file, llama_model};
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Every GGUF file starts with these bytes.
+pub(crate) const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+
+/// The magic numbers of the pre-GGUF file formats (ggml, ggmf, ggjt), as little endian u32 values.
+const LEGACY_MAGICS: [u32; 3] = [0x67676d6c, 0x67676d66, 0x67676a74];
+
+impl LModel {
+    /// Load the model weights from the path in config.
+    /// Only the model level settings of the config (eg. n_gpu_layers, use_mlock) are used here;
+    /// the context level settings are applied by `LContext::with_model`.
+    pub fn new(mut config: LContextConfig) -> Result<LModel, LError> {
+        LModel::load(&mut config)
+    }
+
+    pub(crate) fn load(config: &mut LContextConfig) -> Result<LModel, LError> {
+        LModel::validate_model_file(&config.model_path)?;
+        let model_path = config.model_path.to_string_lossy();
+        let model_path_c = CString::new(model_path.as_ref())?;
+        config.reset_progress();
+        let model = unsafe {
+            let params = config.native_ptr();
+            llama_load_model_from_file(model_path_c.as_ptr(), params)
+        };
+        if config.is_cancelled() {
+            if !model.is_null() {
+                unsafe { llama_free_model(model) };
+            }
+            return Err(LError::LoadCancelled(config.model_path.clone()));
+        }
+        if model.is_null() {
+            return Err(LError::ModelLoadFailed(config.model_path.clone()));
+        }
+        Ok(LModel {
+            handle: Arc::new(LModelHandle {
+                model_path: config.model_path.clone(),
+                model,
+            }),
+        })
+    }
+
+    /// Check the model file looks loadable before handing it to llama.cpp, which
+    /// reports most failures only on stderr.
+    fn validate_model_file(path: &Path) -> Result<(), LError> {
+        let metadata = match path.metadata() {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Err(LError::ModelFileMissing(path.to_path_buf())),
+            Err(err) => return Err(LError::ModelFileUnreadable(path.to_path_buf(), err.to_string())),
+        };
+        if !metadata.is_file() {
+            return Err(LError::ModelFileUnreadable(path.to_path_buf(), "path is not a file".to_string()));
+        }
+
+        let mut magic = [0u8; 4];
+        let mut file = File::open(path).map_err(|err| LError::ModelFileUnreadable(path.to_path_buf(), err.to_string()))?;
+        if let Err(err) = file.read_exact(&mut magic) {
+            return Err(match err.kind() {
+                ErrorKind::UnexpectedEof => LError::UnsupportedModelFormat(path.to_path_buf(), "file is too short to be a model".to_string()),
+                _ => LError::ModelFileUnreadable(path.to_path_buf(), err.to_string()),
+            });
+        }
+
+        if &magic == GGUF_MAGIC {
+            return Ok(());
+        }
+        if LEGACY_MAGICS.contains(&u32::from_le_bytes(magic)) {
+            return Err(LError::UnsupportedModelFormat(
+                path.to_path_buf(),
+                "legacy GGML model; convert it to GGUF using the llama.cpp conversion scripts".to_string(),
+            ));
+        }
+        Err(LError::UnsupportedModelFormat(
+            path.to_path_buf(),
+            format!("missing GGUF header, found {:?}", magic),
+        ))
+    }
+
+    /// The path this model was loaded from
+    pub fn path(&self) -> &Path {
+        &self.handle.model_path
+    }
+
+    /// The chat template stored in the model file as `tokenizer.chat_template`, if any.
+    /// This is usually a Jinja template; see `LChatTemplate::from_context`.
+    pub fn chat_template(&self) -> Result<Option<String>, LError> {
+        read_string_metadata(self.path(), "tokenizer.chat_template")
+    }
+
+    pub(crate) unsafe fn native_ptr(&self) -> *mut llama_model {
+        self.handle.model
+    }
+}
+
+// The model weights are immutable once loaded; llama.cpp only reads from them while
+// evaluating a context, so the handle can be shared freely between threads.
+unsafe impl Send for LModelHandle {}
+unsafe impl Sync for LModelHandle {}
+
+// The llama.cpp backend is shared by every model in the process, so it is left running when a model is
+// dropped; freeing it here would tear it down under any other model that is still loaded.
+impl Drop for LModelHandle {
+    fn drop(&mut self) {
+        unsafe {
+            llama_free_model(self.model);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::domain::LSampleMode;
 use crate::LSampleParams;
 
 impl Default for LSampleParams {
@@ -9,7 +10,12 @@ impl Default for LSampleParams {
             repeat_penalty: 1.1f32,
             tfs_z: 1f32,
             typical_p: 1f32,
+            min_p: 0f32,
+            frequency_penalty: 0f32,
+            presence_penalty: 0f32,
+            penalize_newline: true,
             repeat_history_length: 1024,
+            mode: LSampleMode::Standard,
         }
     }
 }
@@ -0,0 +1,164 @@
+use crate::domain::{LCandidates, LMinP, LPenalties, LSampler, LSamplerChain, LTailFree, LTemperature, LTopK, LTopP, LTypical};
+use crate::{LContext, LError, LLogitBias, LSampleParams};
+use llama_cpp_sys::{
+    llama_sample_frequency_and_presence_penalties, llama_sample_repetition_penalty, llama_sample_tail_free, llama_sample_temperature,
+    llama_sample_top_k, llama_sample_top_p, llama_sample_typical, llama_token_eos, llama_token_nl,
+};
+
+impl LSamplerChain {
+    pub fn new() -> LSamplerChain {
+        Default::default()
+    }
+
+    /// Add sampler to the end of the chain.
+    pub fn push(&mut self, sampler: impl LSampler + 'static) {
+        self.samplers.push(Box::new(sampler));
+    }
+
+    /// Add sampler to the end of the chain, for building a chain in a single expression.
+    pub fn with(mut self, sampler: impl LSampler + 'static) -> LSamplerChain {
+        self.push(sampler);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samplers.is_empty()
+    }
+
+    /// The chain `LContext::sample` uses for `LSampleMode::Standard`.
+    pub fn from_params(params: &LSampleParams) -> LSamplerChain {
+        LSamplerChain::new()
+            .with(LPenalties::from(params))
+            .with(LTopK(params.top_k))
+            .with(LTailFree(params.tfs_z))
+            .with(LTypical(params.typical_p))
+            .with(LTopP(params.top_p))
+            .with(LMinP(params.min_p))
+            .with(LTemperature(params.temp))
+    }
+}
+
+impl LSampler for LSamplerChain {
+    fn apply(&mut self, context: &LContext, candidates: &mut LCandidates) -> Result<(), LError> {
+        for sampler in self.samplers.iter_mut() {
+            sampler.apply(context, candidates)?;
+        }
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&LContext, &mut LCandidates) -> Result<(), LError> + Send> LSampler for F {
+    fn apply(&mut self, context: &LContext, candidates: &mut LCandidates) -> Result<(), LError> {
+        self(context, candidates)
+    }
+}
+
+impl LSampler for LTopK {
+    fn apply(&mut self, context: &LContext, candidates: &mut LCandidates) -> Result<(), LError> {
+        unsafe { candidates.with_native(|array| llama_sample_top_k(context.native_ptr(), array, self.0, 1)) };
+        Ok(())
+    }
+}
+
+impl LSampler for LTailFree {
+    fn apply(&mut self, context: &LContext, candidates: &mut LCandidates) -> Result<(), LError> {
+        unsafe { candidates.with_native(|array| llama_sample_tail_free(context.native_ptr(), array, self.0, 1)) };
+        Ok(())
+    }
+}
+
+impl LSampler for LTypical {
+    fn apply(&mut self, context: &LContext, candidates: &mut LCandidates) -> Result<(), LError> {
+        unsafe { candidates.with_native(|array| llama_sample_typical(context.native_ptr(), array, self.0, 1)) };
+        Ok(())
+    }
+}
+
+impl LSampler for LTopP {
+    fn apply(&mut self, context: &LContext, candidates: &mut LCandidates) -> Result<(), LError> {
+        unsafe { candidates.with_native(|array| llama_sample_top_p(context.native_ptr(), array, self.0, 1)) };
+        Ok(())
+    }
+}
+
+impl LSampler for LMinP {
+    fn apply(&mut self, _context: &LContext, candidates: &mut LCandidates) -> Result<(), LError> {
+        candidates.min_p(self.0, 1);
+        Ok(())
+    }
+}
+
+impl LSampler for LTemperature {
+    fn apply(&mut self, context: &LContext, candidates: &mut LCandidates) -> Result<(), LError> {
+        // Dividing by zero would turn every probability into NaN; the limit is the most likely token
+        if self.0 <= 0f32 {
+            candidates.sort();
+            candidates.truncate(1);
+            return Ok(());
+        }
+        unsafe { candidates.with_native(|array| llama_sample_temperature(context.native_ptr(), array, self.0)) };
+        Ok(())
+    }
+}
+
+impl From<&LSampleParams> for LPenalties {
+    fn from(params: &LSampleParams) -> Self {
+        LPenalties {
+            repeat_penalty: params.repeat_penalty,
+            frequency_penalty: params.frequency_penalty,
+            presence_penalty: params.presence_penalty,
+            history_length: params.repeat_history_length,
+            penalize_newline: params.penalize_newline,
+        }
+    }
+}
+
+impl LSampler for LPenalties {
+    fn apply(&mut self, context: &LContext, candidates: &mut LCandidates) -> Result<(), LError> {
+        let newline = unsafe { llama_token_nl(context.native_ptr()) };
+        let exempt_tokens = context.penalty_exempt.iter().chain(Some(&newline).filter(|_| !self.penalize_newline));
+
+        // Remember the logits of exempt tokens, so they can be restored after the penalties.
+        let exempt: Vec<_> = exempt_tokens
+            .filter_map(|token| candidates.iter().find(|candidate| candidate.id == *token).copied())
+            .collect();
+
+        let history_length = self.history_length.min(context.token_history.len());
+        let history = &context.token_history[context.token_history.len() - history_length..];
+        unsafe {
+            candidates.with_native(|array| {
+                llama_sample_repetition_penalty(context.native_ptr(), array, history.as_ptr(), history.len(), self.repeat_penalty);
+                llama_sample_frequency_and_presence_penalties(
+                    context.native_ptr(),
+                    array,
+                    history.as_ptr(),
+                    history.len(),
+                    self.frequency_penalty,
+                    self.presence_penalty,
+                );
+            });
+        }
+
+        if !exempt.is_empty() {
+            for candidate in candidates.as_mut_slice() {
+                if let Some(original) = exempt.iter().find(|original| original.id == candidate.id) {
+                    candidate.logit = original.logit;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LSampler for LLogitBias {
+    fn apply(&mut self, context: &LContext, candidates: &mut LCandidates) -> Result<(), LError> {
+        // As in `LContext::sample`, EOS is left to the grammar when there is one
+        let eos = Some(unsafe { llama_token_eos(context.native_ptr()) }).filter(|_| context.grammar.is_none());
+        self.apply_to(eos, candidates);
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use crate::domain::llama_logits::log_sum_exp;
+use crate::{LContext, LError, LScoreResult, LToken, LTokenSequence};
+use llama_cpp_sys::llama_token_bos;
+
+impl LScoreResult {
+    fn from_logprobs(token_logprobs: Vec<f32>) -> LScoreResult {
+        let logprob: f64 = token_logprobs.iter().map(|logprob| *logprob as f64).sum();
+        let perplexity = (-logprob / token_logprobs.len() as f64).exp();
+        LScoreResult {
+            token_logprobs,
+            logprob,
+            perplexity,
+        }
+    }
+}
+
+impl LContext {
+    /// Evaluate tokens from the start of the context, and score every token after the first by how likely
+    /// the model found it given the tokens before it. Requires `LContextConfig::logits_all`.
+    /// This discards anything previously evaluated.
+    pub fn score(&mut self, tokens: &LTokenSequence, num_threads: usize) -> Result<LScoreResult, LError> {
+        if tokens.len() < 2 {
+            return Err(LError::InvalidInput("at least two tokens are needed to score a sequence".to_string()));
+        }
+        self.require_logits_all()?;
+
+        let mut token_logprobs = Vec::with_capacity(tokens.len() - 1);
+        self.evaluate_scored(tokens, 1, num_threads, &mut token_logprobs)?;
+        Ok(LScoreResult::from_logprobs(token_logprobs))
+    }
+
+    /// The perplexity of the model over text, which may be longer than the context.
+    /// Like the llama.cpp perplexity tool, the text is split into chunks that fill the context, each evaluated
+    /// from scratch starting with BOS, and only the second half of each chunk is scored, so every scored token
+    /// has at least half a context of history. Text that does not fill a whole chunk at the end is ignored.
+    /// Requires `LContextConfig::logits_all`, and discards anything previously evaluated.
+    pub fn perplexity(&mut self, text: &str, num_threads: usize) -> Result<LScoreResult, LError> {
+        self.require_logits_all()?;
+        let tokens = self.tokenize(text)?;
+
+        let chunk_len = self.max_chunk_len();
+        let chunk_count = tokens.len() / chunk_len;
+        if chunk_count == 0 {
+            return Err(LError::InvalidInput(format!(
+                "text is {} tokens, but at least {} tokens are needed to fill the context",
+                tokens.len(),
+                chunk_len
+            )));
+        }
+
+        let bos = LToken::from(unsafe { llama_token_bos(self.native_ptr()) });
+        let mut token_logprobs = Vec::new();
+        for chunk_index in 0..chunk_count {
+            let mut chunk = tokens.suffix(chunk_index * chunk_len + 1);
+            chunk.resize(chunk_len - 1);
+            let mut chunk_tokens = LTokenSequence::new();
+            chunk_tokens.push(bos.clone());
+            chunk_tokens.extend(&chunk);
+            self.evaluate_scored(&chunk_tokens, chunk_len / 2 + 1, num_threads, &mut token_logprobs)?;
+        }
+        Ok(LScoreResult::from_logprobs(token_logprobs))
+    }
+
+    fn require_logits_all(&self) -> Result<(), LError> {
+        if !self.logits_all {
+            return Err(LError::LogitsUnavailable(
+                "scoring requires the logits of every token; set logits_all in the context config".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Evaluate tokens from the start of the context, appending to logprobs the log-probability of each
+    /// token from first_scored onwards, as predicted by the logits of the token before it.
+    fn evaluate_scored(&mut self, tokens: &LTokenSequence, first_scored: usize, num_threads: usize, logprobs: &mut Vec<f32>) -> Result<(), LError> {
+        let ids: Vec<usize> = tokens.iter().map(|token| token.id()).collect();
+        self.truncate(0);
+        self.step_batches(tokens, num_threads, |context, positions| {
+            // The logits at each position predict the token after it
+            let logits = context.all_logits()?;
+            for position in positions {
+                let next = position + 1;
+                if next < first_scored || next >= ids.len() {
+                    continue;
+                }
+                if let Some(row) = logits.get(position) {
+                    logprobs.push(row[ids[next]] - log_sum_exp(row));
+                }
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,23 @@
+use crate::LSessionState;
+use std::path::Path;
+
+impl LSessionState {
+    /// The size of the state data in bytes
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The raw state data, as produced by llama.cpp
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The path of the model this state was captured from
+    pub fn model_path(&self) -> &Path {
+        &self.model_path
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{LContext, LError, LToken};
 use llama_cpp_sys::{llama_token, llama_token_to_piece};
-use std::ffi::CStr;
+use std::ffi::c_char;
 
 impl From<llama_token> for LToken {
     fn from(value: llama_token) -> Self {
@@ -9,39 +9,44 @@ impl From<llama_token> for LToken {
 }
 
 impl LToken {
-    pub fn as_string(&self, context: &mut LContext) -> Result<String, LError> {
+    pub fn as_string(&self, context: &LContext) -> Result<String, LError> {
         if !self.has_str_value(context) {
             return Err(LError::TokenizationError("No string repr available for token".to_string()));
         }
-        let str_value = unsafe {
-            let ctx = context.native_ptr();
-            let token = self.native_value();
+        let bytes = self.as_bytes(context)?;
+        String::from_utf8(bytes).map_err(|failure| {
+            LError::InvalidCString(format!(
+                "Unable to render token {} bytes {:?} as string: {}",
+                self.0,
+                failure.as_bytes(),
+                failure.utf8_error()
+            ))
+        })
+    }
+
+    /// The raw bytes of the text of this token. A byte-level token may be only part of a UTF-8 character,
+    /// so use `LDetokenizer` or `LContext::detokenize` to convert tokens to text.
+    pub fn as_bytes(&self, context: &LContext) -> Result<Vec<u8>, LError> {
+        let mut buffer: Vec<c_char> = vec![0; 32];
+        let mut piece_length = self.to_piece(context, &mut buffer);
+
+        // A negative length is the size of buffer the piece needs
+        if piece_length < 0 {
+            buffer.resize(piece_length.unsigned_abs() as usize, 0);
+            piece_length = self.to_piece(context, &mut buffer);
+        }
+        if piece_length < 0 {
+            return Err(LError::InvalidCString(format!(
+                "Unable to render token {}: llama_token_to_piece() returned {}",
+                self.0, piece_length
+            )));
+        }
+        buffer.truncate(piece_length as usize);
+        Ok(buffer.into_iter().map(|i| i as u8).collect())
+    }
 
-            context.token_buffer.fill(0);
-            let piece_length = llama_token_to_piece(ctx, token, context.token_buffer.as_mut_ptr(), context.token_buffer.len() as i32);
-            if piece_length < 0 {
-                return Err(LError::InvalidCString(format!(
-                    "Unable to render token {}: llama_token_to_piece() returned -1",
-                    token,
-                )));
-            }
-            let c_str = CStr::from_ptr(context.token_buffer.as_ptr());
-            match c_str.to_str() {
-                Ok(str_slice) => str_slice.to_string(),
-                Err(failure) => {
-                    return Err(LError::InvalidCString(format!(
-                        "Unable to render token {} bytes {:?} as string: {}",
-                        token,
-                        &context.token_buffer[0..piece_length as usize]
-                            .iter()
-                            .map(|i| *i as u8)
-                            .collect::<Vec<u8>>(),
-                        failure
-                    )));
-                }
-            }
-        };
-        Ok(str_value)
+    fn to_piece(&self, context: &LContext, buffer: &mut [c_char]) -> i32 {
+        unsafe { llama_token_to_piece(context.native_ptr(), self.0, buffer.as_mut_ptr(), buffer.len() as i32) }
     }
 
     pub fn default_token() -> llama_cpp_sys::llama_token {
@@ -60,6 +65,11 @@ impl LToken {
         self.0 == unsafe { llama_cpp_sys::llama_token_bos(context.native_ptr()) }
     }
 
+    /// The index of this token in the vocabulary; eg. to look up its logit in `LContext::logits`.
+    pub fn id(&self) -> usize {
+        self.0 as usize
+    }
+
     pub(crate) unsafe fn native_value(&self) -> llama_cpp_sys::llama_token {
         self.0
     }
@@ -37,6 +37,23 @@ impl LTokenSequence {
         self.tokens.push(value);
     }
 
+    /// Append all the tokens in other to the end of this sequence.
+    pub fn extend(&mut self, other: &LTokenSequence) {
+        self.tokens.extend_from_slice(&other.tokens);
+    }
+
+    /// Return a new sequence containing the tokens from start to the end of this sequence.
+    pub fn suffix(&self, start: usize) -> LTokenSequence {
+        LTokenSequence {
+            tokens: self.tokens[start.min(self.tokens.len())..].to_vec(),
+        }
+    }
+
+    /// The number of leading tokens this sequence has in common with other.
+    pub fn common_prefix_length(&self, other: &LTokenSequence) -> usize {
+        self.tokens.iter().zip(other.tokens.iter()).take_while(|(a, b)| a == b).count()
+    }
+
     pub fn clear(&mut self) {
         if self.is_empty() {
             return;
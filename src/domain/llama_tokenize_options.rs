@@ -0,0 +1,11 @@
+use crate::LTokenizeOptions;
+
+impl Default for LTokenizeOptions {
+    fn default() -> Self {
+        LTokenizeOptions {
+            add_bos: true,
+            parse_special: false,
+            add_eos: false,
+        }
+    }
+}
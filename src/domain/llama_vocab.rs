@@ -0,0 +1,182 @@
+use crate::{LContext, LError, LToken, LTokenType, LVocab, LVocabEntry};
+use llama_cpp_sys::{
+    llama_token, llama_token_bos, llama_token_eos, llama_token_get_score, llama_token_get_text, llama_token_get_type, llama_token_nl,
+    llama_token_type, llama_token_type_LLAMA_TOKEN_TYPE_BYTE, llama_token_type_LLAMA_TOKEN_TYPE_CONTROL, llama_token_type_LLAMA_TOKEN_TYPE_NORMAL,
+    llama_token_type_LLAMA_TOKEN_TYPE_UNKNOWN, llama_token_type_LLAMA_TOKEN_TYPE_UNUSED, llama_token_type_LLAMA_TOKEN_TYPE_USER_DEFINED,
+};
+use std::collections::HashMap;
+use std::ffi::CStr;
+
+/// The vocabulary text of the code infill tokens, as used by CodeLlama.
+const INFILL_PREFIX: &str = "▁<PRE>";
+const INFILL_MIDDLE: &str = "▁<MID>";
+const INFILL_SUFFIX: &str = "▁<SUF>";
+const INFILL_END: &str = "▁<EOT>";
+
+impl From<llama_token_type> for LTokenType {
+    fn from(value: llama_token_type) -> Self {
+        match value {
+            llama_token_type_LLAMA_TOKEN_TYPE_NORMAL => LTokenType::Normal,
+            llama_token_type_LLAMA_TOKEN_TYPE_UNKNOWN => LTokenType::Unknown,
+            llama_token_type_LLAMA_TOKEN_TYPE_CONTROL => LTokenType::Control,
+            llama_token_type_LLAMA_TOKEN_TYPE_USER_DEFINED => LTokenType::UserDefined,
+            llama_token_type_LLAMA_TOKEN_TYPE_UNUSED => LTokenType::Unused,
+            llama_token_type_LLAMA_TOKEN_TYPE_BYTE => LTokenType::Byte,
+            _ => LTokenType::Undefined,
+        }
+    }
+}
+
+impl<'a> LVocab<'a> {
+    pub(crate) fn new(context: &'a LContext) -> LVocab<'a> {
+        LVocab { context }
+    }
+
+    /// The number of tokens in the vocabulary
+    pub fn len(&self) -> usize {
+        self.context.n_vocab()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The token with id, or None if it is not in the vocabulary.
+    /// Fails if llama.cpp can't render the piece of the token.
+    pub fn get(&self, id: usize) -> Result<Option<LVocabEntry>, LError> {
+        if id >= self.len() {
+            return Ok(None);
+        }
+        self.entry(LToken::from(id as llama_token)).map(Some)
+    }
+
+    /// Every token in the vocabulary, in order of id; there is always one item for each token.
+    pub fn iter(&self) -> impl Iterator<Item = Result<LVocabEntry, LError>> + '_ {
+        (0..self.len()).map(|id| self.entry(LToken::from(id as llama_token)))
+    }
+
+    fn entry(&self, token: LToken) -> Result<LVocabEntry, LError> {
+        let id = unsafe { token.native_value() };
+        let score = unsafe { llama_token_get_score(self.context.native_ptr(), id) };
+        let piece = token.as_bytes(self.context)?;
+        Ok(LVocabEntry {
+            text: self.token_text(id),
+            piece,
+            score,
+            token_type: self.token_type(id),
+            token,
+        })
+    }
+
+    /// The text of token as stored in the vocabulary, or None if it is not in the vocabulary.
+    pub fn text(&self, token: &LToken) -> Option<String> {
+        if token.id() >= self.len() {
+            return None;
+        }
+        Some(self.token_text(unsafe { token.native_value() }))
+    }
+
+    /// The text of the token with id, which must be in the vocabulary.
+    fn token_text(&self, id: llama_token) -> String {
+        unsafe { CStr::from_ptr(llama_token_get_text(self.context.native_ptr(), id)) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// The type of the token with id, which must be in the vocabulary.
+    fn token_type(&self, id: llama_token) -> LTokenType {
+        LTokenType::from(unsafe { llama_token_get_type(self.context.native_ptr(), id) })
+    }
+
+    pub fn bos(&self) -> LToken {
+        LToken::from(unsafe { llama_token_bos(self.context.native_ptr()) })
+    }
+
+    pub fn eos(&self) -> LToken {
+        LToken::from(unsafe { llama_token_eos(self.context.native_ptr()) })
+    }
+
+    pub fn newline(&self) -> LToken {
+        LToken::from(unsafe { llama_token_nl(self.context.native_ptr()) })
+    }
+
+    /// The token that starts the prefix of a fill-in-the-middle prompt, if the model supports infill.
+    pub fn infill_prefix(&self) -> Option<LToken> {
+        self.find_text(INFILL_PREFIX)
+    }
+
+    /// The token after which the model generates the middle of a fill-in-the-middle prompt.
+    pub fn infill_middle(&self) -> Option<LToken> {
+        self.find_text(INFILL_MIDDLE)
+    }
+
+    /// The token that starts the suffix of a fill-in-the-middle prompt.
+    pub fn infill_suffix(&self) -> Option<LToken> {
+        self.find_text(INFILL_SUFFIX)
+    }
+
+    /// The token that ends the generated middle of a fill-in-the-middle prompt.
+    pub fn infill_end(&self) -> Option<LToken> {
+        self.find_text(INFILL_END)
+    }
+
+    /// The tokens with special meaning to the model, like BOS and EOS.
+    pub fn control_tokens(&self) -> impl Iterator<Item = LToken> + '_ {
+        (0..self.len() as llama_token)
+            .filter(|id| self.token_type(*id) == LTokenType::Control)
+            .map(LToken::from)
+    }
+
+    /// The tokens that `LTokenizeOptions::parse_special` recognises in text: control tokens, and
+    /// user-defined tokens like the `<|im_start|>` of ChatML models.
+    pub fn special_tokens(&self) -> impl Iterator<Item = LToken> + '_ {
+        (0..self.len() as llama_token)
+            .filter(|id| matches!(self.token_type(*id), LTokenType::Control | LTokenType::UserDefined))
+            .map(LToken::from)
+    }
+
+    /// The token whose vocabulary text is exactly text; eg. `▁Paris`.
+    /// Where several tokens have the same text, the one with the lowest id is returned.
+    pub fn find_text(&self, text: &str) -> Option<LToken> {
+        let texts = self.context.vocab_texts.get_or_init(|| {
+            let mut texts = HashMap::new();
+            for id in 0..self.len() as llama_token {
+                texts.entry(self.token_text(id)).or_insert(id);
+            }
+            texts
+        });
+        texts.get(text).map(|id| LToken::from(*id))
+    }
+
+    /// The token that produces exactly piece in generated text; eg. ` Paris`.
+    /// Where several tokens produce the same piece, the one the tokenizer produces is preferred: a normal
+    /// token over a byte token, and otherwise the one with the lowest id.
+    pub fn find_piece(&self, piece: &[u8]) -> Option<LToken> {
+        let pieces = self.context.vocab_pieces.get_or_init(|| {
+            let mut pieces: HashMap<Vec<u8>, llama_token> = HashMap::new();
+            for id in 0..self.len() as llama_token {
+                // Tokens whose piece can't be rendered can't be found by piece either
+                let piece = match LToken::from(id).as_bytes(self.context) {
+                    Ok(piece) => piece,
+                    Err(_) => continue,
+                };
+                let replace = match pieces.get(&piece) {
+                    Some(existing) => self.token_type(*existing) == LTokenType::Byte && self.token_type(id) != LTokenType::Byte,
+                    None => true,
+                };
+                if replace {
+                    pieces.insert(piece, id);
+                }
+            }
+            pieces
+        });
+        pieces.get(piece).map(|id| LToken::from(*id))
+    }
+}
+
+impl LContext {
+    /// Inspect the vocabulary of the model.
+    pub fn vocab(&self) -> LVocab<'_> {
+        LVocab::new(self)
+    }
+}
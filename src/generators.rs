@@ -1,79 +1,314 @@
-use crate::{LContext, LError, LSampleParams, LTokenSequence};
+use crate::domain::default_thread_count;
+#[cfg(feature = "serde")]
+use crate::LJsonSchema;
+use crate::{
+    LChatMessage, LChatTemplate, LContext, LDetokenizer, LError, LGrammar, LLogitBias, LSampleParams, LSampleResult, LSamplerChain, LToken,
+    LTokenSequence, LTokenizeOptions,
+};
+use std::collections::VecDeque;
+
+#[cfg(feature = "tokio")]
+mod llama_async_generator;
+mod llama_generator_stream;
+mod llama_stop_sequences;
 
 pub struct LGeneratorParams {
-    /// Generate this number of tokens before halting
+    /// Generate this number of tokens before halting.
+    /// Every generated token counts, so generation stops with exactly this many unless it finishes earlier;
+    /// versions before `LGenerationResult` stopped one token short.
     pub generate_tokens: usize,
 
     /// The number of threads to process with, more is better, but only if your hardware supports it.
     pub worker_thread_count: usize,
 
+    /// How the prompt is tokenized; eg. set `parse_special` for prompts that contain control tokens like `<s>`.
+    pub tokenize_options: LTokenizeOptions,
+
     /// Settings to use for sampling the model
     pub sample_params: LSampleParams,
+
+    /// Sample with this chain instead of `sample_params`
+    pub sampler: Option<LSamplerChain>,
+
+    /// Halt when the output contains any of these; the stop sequence itself is not included in the output.
+    pub stop_sequences: Vec<String>,
+
+    /// Only generate output that matches this grammar
+    pub grammar: Option<LGrammar>,
+
+    /// Adjust the likelihood of specific tokens; eg. set `ban_eos` to always generate `generate_tokens` tokens.
+    pub logit_bias: Option<LLogitBias>,
+
+    /// Report this many of the most likely alternatives for each generated token
+    pub top_logprobs: usize,
+
+    /// Tokens exempt from the repetition, frequency and presence penalties in `sample_params`
+    pub penalty_exempt_tokens: LTokenSequence,
 }
 
 pub struct LGenerator {
     context: LContext,
 }
 
+/// Why a generation stopped producing tokens.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LFinishReason {
+    /// The model produced the end of stream token
+    EndOfStream,
+
+    /// `generate_tokens` tokens were generated
+    TokenLimit,
+
+    /// The output contained one of the stop sequences
+    StopSequence,
+
+    /// The incremental callback returned false
+    Callback,
+
+    /// There was no room left in the context for another token
+    ContextFull,
+}
+
+/// The output of a generation, along with statistics about it.
+#[derive(Clone, Debug)]
+pub struct LGenerationResult {
+    /// The generated text
+    pub text: String,
+
+    /// The generated tokens
+    pub tokens: LTokenSequence,
+
+    pub finish_reason: LFinishReason,
+
+    /// The number of tokens in the prompt
+    pub prompt_tokens: usize,
+
+    /// The number of prompt tokens that were already in the KV cache from a previous generation, and were not evaluated again
+    pub cached_prompt_tokens: usize,
+
+    /// The number of generated tokens
+    pub completion_tokens: usize,
+
+    /// The log-probabilities of each of the generated tokens
+    pub logprobs: Vec<LSampleResult>,
+
+    /// Time spent evaluating the prompt, in milliseconds
+    pub prompt_eval_ms: f64,
+
+    /// Generation speed, excluding the prompt
+    pub tokens_per_second: f64,
+}
+
+/// Finds stop sequences in generated text, including the start of one at the end of text that may be completed
+/// by the next token; see `LGeneratorParams::stop_sequences`. Empty stop sequences never match.
+#[derive(Clone, Debug, Default)]
+pub struct LStopSequences {
+    stop_sequences: Vec<String>,
+}
+
+/// A single token produced by `LGenerator::stream`.
+#[derive(Clone)]
+pub struct LGeneratedToken {
+    /// The sampled token
+    pub token: LToken,
+
+    /// The text of the token; empty for tokens with no text, like BOS.
+    pub text: String,
+
+    /// The log-probability of the token under the model's output distribution, before sampling adjustments.
+    pub logprob: f32,
+
+    /// The most likely tokens at this position and their log-probabilities; see `LGeneratorParams::top_logprobs`.
+    pub top_logprobs: Vec<(LToken, f32)>,
+
+    /// The position of the token in the context.
+    pub position: usize,
+}
+
+/// A generator that runs inference on a blocking tokio worker thread.
+/// Cloning is cheap; clones share the same generator, and concurrent streams run one after another.
+#[cfg(feature = "tokio")]
+#[derive(Clone)]
+pub struct LAsyncGenerator {
+    generator: std::sync::Arc<std::sync::Mutex<LGenerator>>,
+}
+
+/// A `futures::Stream` of generated tokens; see `LAsyncGenerator::stream`.
+/// Dropping the stream stops generation.
+#[cfg(feature = "tokio")]
+pub struct LAsyncTokenStream {
+    receiver: tokio::sync::mpsc::Receiver<Result<LGeneratedToken, LError>>,
+}
+
+/// An iterator over the tokens of a single generation; see `LGenerator::stream`.
+/// Each token is evaluated lazily when the next one is requested, so dropping the stream stops generation immediately.
+pub struct LGeneratorStream<'a> {
+    generator: &'a mut LGenerator,
+    params: LGeneratorParams,
+    prompt: Option<String>,
+    pending: Option<LToken>,
+    generated: usize,
+    finished: bool,
+    finish_reason: Option<LFinishReason>,
+
+    /// The tokens returned by the iterator so far
+    tokens: LTokenSequence,
+    logprobs: Vec<LSampleResult>,
+    prompt_tokens: usize,
+    cached_prompt_tokens: usize,
+
+    /// Converts the generated tokens to text
+    detokenizer: LDetokenizer,
+
+    /// The stop sequences of params
+    stop_sequences: LStopSequences,
+
+    /// Tokens whose text may be the start of a stop sequence, held back until that is decided
+    held: VecDeque<LGeneratedToken>,
+
+    /// Tokens ready to be returned by the iterator
+    ready: VecDeque<LGeneratedToken>,
+}
+
+impl Default for LGeneratorParams {
+    fn default() -> Self {
+        LGeneratorParams {
+            generate_tokens: 128,
+            worker_thread_count: default_thread_count(),
+            tokenize_options: Default::default(),
+            sample_params: Default::default(),
+            sampler: None,
+            stop_sequences: Vec::new(),
+            grammar: None,
+            logit_bias: None,
+            penalty_exempt_tokens: LTokenSequence::new(),
+            top_logprobs: 0,
+        }
+    }
+}
+
 impl LGenerator {
     pub fn new(context: LContext) -> LGenerator {
         LGenerator { context }
     }
 
+    /// The context used for generation; eg. to tokenize text for an `LLogitBias`.
+    pub fn context(&self) -> &LContext {
+        &self.context
+    }
+
     fn generate_no_op(_value: &[String]) -> bool {
         true
     }
 
     pub fn generate(&mut self, prompt: &str, params: LGeneratorParams) -> Result<String, LError> {
-        self.generate_internal(prompt, params, LGenerator::generate_no_op)
+        Ok(self.generate_internal(prompt, params, LGenerator::generate_no_op)?.text)
     }
 
     pub fn generate_incremental(&mut self, prompt: &str, params: LGeneratorParams, callback: impl Fn(&[String]) -> bool) -> Result<String, LError> {
+        Ok(self.generate_internal(prompt, params, callback)?.text)
+    }
+
+    /// Like `generate`, but also report why generation finished, token counts and timings.
+    pub fn generate_result(&mut self, prompt: &str, params: LGeneratorParams) -> Result<LGenerationResult, LError> {
+        self.generate_internal(prompt, params, LGenerator::generate_no_op)
+    }
+
+    /// Like `generate_incremental`, but also report why generation finished, token counts and timings.
+    pub fn generate_incremental_result(
+        &mut self,
+        prompt: &str,
+        params: LGeneratorParams,
+        callback: impl Fn(&[String]) -> bool,
+    ) -> Result<LGenerationResult, LError> {
         self.generate_internal(prompt, params, callback)
     }
 
-    pub fn generate_internal(&mut self, prompt: &str, params: LGeneratorParams, callback: impl Fn(&[String]) -> bool) -> Result<String, LError> {
-        // Load prompt
-        let prompt_tokens = self.context.tokenize(prompt)?;
-        let mut token_stream = prompt_tokens;
+    /// Generate JSON matching schema, and deserialize it into T.
+    /// The output is constrained by a grammar, so it is always valid for the schema unless generation
+    /// stops early; make sure generate_tokens is large enough for the whole value.
+    #[cfg(feature = "serde")]
+    pub fn generate_json<T: serde::de::DeserializeOwned>(
+        &mut self,
+        prompt: &str,
+        schema: &LJsonSchema,
+        mut params: LGeneratorParams,
+    ) -> Result<T, LError> {
+        params.grammar = Some(LGrammar::from_json_schema(schema)?);
+        let result = self.generate_result(prompt, params)?;
+        serde_json::from_str(&result.text).map_err(|err| {
+            LError::JsonError(format!(
+                "failed to deserialize output, generation finished with {:?}: {}",
+                result.finish_reason, err
+            ))
+        })
+    }
 
-        // The query buffer is a window into the token stream to use for inference
-        let mut gen_buffer = LTokenSequence::new();
-        gen_buffer.resize(1); // Always generate a single new token per round
+    /// Generate the assistant's reply to messages, formatted as a prompt with template.
+    /// Control tokens in the prompt, like `</s>` between turns, are tokenized as tokens, and the
+    /// stop sequences of the template are added to those in params.
+    pub fn chat(&mut self, messages: &[LChatMessage], template: &LChatTemplate, mut params: LGeneratorParams) -> Result<LGenerationResult, LError> {
+        let prompt = template.render(messages, true)?;
+        params.tokenize_options.parse_special = true;
+        for stop in template.stop_sequences() {
+            if !params.stop_sequences.contains(&stop) {
+                params.stop_sequences.push(stop);
+            }
+        }
+        self.generate_result(&prompt, params)
+    }
 
-        // Initialize with prompt
-        self.context.load_prompt(&token_stream, params.worker_thread_count)?;
+    /// Generate tokens for prompt one at a time.
+    /// Nothing is evaluated until the first token is requested, so errors loading the prompt are returned by the iterator.
+    pub fn stream(&mut self, prompt: &str, params: LGeneratorParams) -> LGeneratorStream<'_> {
+        LGeneratorStream::new(self, prompt, params)
+    }
 
+    pub(crate) fn generate_internal(
+        &mut self,
+        prompt: &str,
+        params: LGeneratorParams,
+        callback: impl Fn(&[String]) -> bool,
+    ) -> Result<LGenerationResult, LError> {
+        let mut stream = self.stream(prompt, params);
         let mut token_strings = Vec::new();
-        for _ in 0..(params.generate_tokens - 1) {
-            gen_buffer.clear();
-            gen_buffer.copy_trailing(&token_stream);
-
-            // Invoke model
-            self.context.step(&gen_buffer, params.worker_thread_count)?;
+        let mut halted = false;
+        for generated in stream.by_ref() {
+            let generated = generated?;
+            if generated.text.is_empty() {
+                continue;
+            }
+            token_strings.push(generated.text);
 
-            // Sample result
-            let token = self.context.sample(Some(params.sample_params))?;
-            if token.is_end_of_stream(&self.context) {
+            // Halt early if the incremental thinks we're done
+            if !callback(&token_strings) {
+                halted = true;
                 break;
             }
+        }
 
-            // Save token
-            token_stream.push(token.clone());
-
-            // Incremental completion callback
-            if token.has_str_value(&self.context) {
-                let token_string = token.as_string(&mut self.context)?;
-                token_strings.push(token_string);
+        // Convert token stream back into a string
+        let finish_reason = if halted { Some(LFinishReason::Callback) } else { None };
+        Ok(stream.result(token_strings.join(""), finish_reason))
+    }
 
-                // Halt early if the incremental thinks we're done
-                if !callback(&token_strings) {
-                    break;
-                }
-            }
+    /// Evaluate the prompt, reusing the longest prefix of it already in the KV cache from a previous
+    /// generation (eg. a fixed system prompt or the chat history), so only the new suffix is evaluated.
+    /// Returns the number of prompt tokens that were reused.
+    fn load_prompt(&mut self, prompt: &LTokenSequence, worker_thread_count: usize) -> Result<usize, LError> {
+        if prompt.is_empty() {
+            return Err(LError::TokenizationError("cannot generate from an empty prompt".to_string()));
         }
 
-        // Convert token stream back into a string
-        Ok(token_strings.join(""))
+        // The final prompt token is always evaluated again, since its logits are needed for sampling.
+        let reusable = self.context.evaluated().common_prefix_length(prompt).min(prompt.len() - 1);
+        if reusable == 0 {
+            self.context.load_prompt(prompt, worker_thread_count)?;
+        } else {
+            self.context.reset_mirostat();
+            self.context.truncate(reusable);
+            self.context.step(&prompt.suffix(reusable), worker_thread_count)?;
+        }
+        Ok(reusable)
     }
 }
@@ -0,0 +1,58 @@
+use crate::generators::{LAsyncGenerator, LAsyncTokenStream};
+use crate::{LError, LGeneratedToken, LGenerator, LGeneratorParams};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// The number of tokens the worker may generate ahead of the consumer before it blocks.
+const STREAM_BUFFER_SIZE: usize = 16;
+
+impl LAsyncGenerator {
+    pub fn new(generator: LGenerator) -> LAsyncGenerator {
+        LAsyncGenerator {
+            generator: Arc::new(Mutex::new(generator)),
+        }
+    }
+
+    /// Generate tokens for prompt on a blocking worker, see `LGenerator::stream`.
+    /// This must be called from within a tokio runtime.
+    pub fn stream(&self, prompt: &str, params: LGeneratorParams) -> LAsyncTokenStream {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER_SIZE);
+        let generator = self.generator.clone();
+        let prompt = prompt.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut generator = match generator.lock() {
+                Ok(generator) => generator,
+                Err(_) => {
+                    let _ = sender.blocking_send(Err(LError::ApiError(
+                        "generator is unusable after a panic in a previous generation".to_string(),
+                    )));
+                    return;
+                }
+            };
+
+            let mut tokens = generator.stream(&prompt, params);
+            while !sender.is_closed() {
+                let Some(generated) = tokens.next() else {
+                    break;
+                };
+
+                // Blocks while the channel is full, and fails once the stream has been dropped.
+                if sender.blocking_send(generated).is_err() {
+                    break;
+                }
+            }
+        });
+        LAsyncTokenStream { receiver }
+    }
+}
+
+impl Stream for LAsyncTokenStream {
+    type Item = Result<LGeneratedToken, LError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
@@ -0,0 +1,182 @@
+use crate::generators::{LFinishReason, LGeneratedToken, LGenerationResult, LGeneratorStream, LStopSequences};
+use crate::{LDetokenizer, LError, LGenerator, LGeneratorParams, LSampleResult, LTokenSequence};
+use std::collections::VecDeque;
+
+impl<'a> LGeneratorStream<'a> {
+    pub(crate) fn new(generator: &'a mut LGenerator, prompt: &str, params: LGeneratorParams) -> LGeneratorStream<'a> {
+        LGeneratorStream {
+            generator,
+            stop_sequences: LStopSequences::new(&params.stop_sequences),
+            params,
+            prompt: Some(prompt.to_string()),
+            pending: None,
+            generated: 0,
+            finished: false,
+            finish_reason: None,
+            tokens: LTokenSequence::new(),
+            logprobs: Vec::new(),
+            prompt_tokens: 0,
+            cached_prompt_tokens: 0,
+            detokenizer: LDetokenizer::new(),
+            held: VecDeque::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Hold back a generated token until we know its text is not part of a stop sequence.
+    fn push_generated(&mut self, generated: LGeneratedToken) {
+        self.held.push_back(generated);
+        let held_text: String = self.held.iter().map(|held| held.text.as_str()).collect();
+
+        // Release the text before the stop sequence and halt
+        if let Some(stop_offset) = self.stop_sequences.find(&held_text) {
+            let mut offset = 0;
+            for mut held in self.held.drain(..) {
+                if offset >= stop_offset {
+                    break;
+                }
+                let end = offset + held.text.len();
+                if end > stop_offset {
+                    held.text.truncate(stop_offset - offset);
+                }
+                offset = end;
+                self.ready.push_back(held);
+            }
+            self.finish(LFinishReason::StopSequence);
+            return;
+        }
+
+        // Release every token that ends before a possible partial match; a token that ends partway through
+        // a character is held too, so the rest of the character can be added to it if the stream ends.
+        let release_before = self.stop_sequences.partial_start(&held_text);
+        let incomplete = usize::from(self.detokenizer.has_pending());
+        let mut offset = 0;
+        while self.held.len() > incomplete {
+            let held = &self.held[0];
+            offset += held.text.len();
+            if offset > release_before {
+                break;
+            }
+            self.ready.extend(self.held.pop_front());
+        }
+    }
+
+    /// Why the stream stopped, once it has finished.
+    pub fn finish_reason(&self) -> Option<LFinishReason> {
+        self.finish_reason
+    }
+
+    /// Summarize the generation; finish_reason overrides the reason the stream itself recorded.
+    pub(crate) fn result(self, text: String, finish_reason: Option<LFinishReason>) -> LGenerationResult {
+        let timings = self.generator.context.timings();
+        let tokens_per_second = if timings.eval_ms > 0f64 {
+            timings.eval_count as f64 * 1000f64 / timings.eval_ms
+        } else {
+            0f64
+        };
+        LGenerationResult {
+            text,
+            completion_tokens: self.tokens.len(),
+            tokens: self.tokens,
+            logprobs: self.logprobs,
+            finish_reason: finish_reason.or(self.finish_reason).unwrap_or(LFinishReason::TokenLimit),
+            prompt_tokens: self.prompt_tokens,
+            cached_prompt_tokens: self.cached_prompt_tokens,
+            prompt_eval_ms: timings.prompt_eval_ms,
+            tokens_per_second,
+        }
+    }
+
+    fn finish(&mut self, finish_reason: LFinishReason) {
+        self.finished = true;
+        self.finish_reason = Some(finish_reason);
+    }
+
+    fn next_token(&mut self) -> Result<Option<LGeneratedToken>, LError> {
+        if self.generated >= self.params.generate_tokens {
+            self.finish(LFinishReason::TokenLimit);
+            return Ok(None);
+        }
+
+        // Either load the prompt, or evaluate the token we returned last time
+        let worker_thread_count = self.params.worker_thread_count;
+        if let Some(prompt) = self.prompt.take() {
+            let prompt_tokens = self.generator.context.tokenize_with(&prompt, self.params.tokenize_options)?;
+            self.generator.context.reset_timings();
+            self.generator.context.set_grammar(self.params.grammar.as_ref());
+            self.generator.context.set_logit_bias(self.params.logit_bias.clone());
+            self.generator.context.set_penalty_exempt_tokens(&self.params.penalty_exempt_tokens);
+            self.cached_prompt_tokens = self.generator.load_prompt(&prompt_tokens, worker_thread_count)?;
+            self.prompt_tokens = prompt_tokens.len();
+        } else if let Some(token) = self.pending.take() {
+            if self.generator.context.evaluated().len() + 1 >= self.generator.context.n_ctx() {
+                self.finish(LFinishReason::ContextFull);
+                return Ok(None);
+            }
+            let mut gen_buffer = LTokenSequence::new();
+            gen_buffer.push(token);
+            self.generator.context.step(&gen_buffer, worker_thread_count)?;
+        }
+
+        // Sample result
+        let context = &mut self.generator.context;
+        let token = match self.params.sampler.as_mut() {
+            Some(sampler) => context.sample_with_chain(sampler)?,
+            None => context.sample(Some(self.params.sample_params))?,
+        };
+        if token.is_end_of_stream(context) {
+            self.finish(LFinishReason::EndOfStream);
+            return Ok(None);
+        }
+
+        let text = self.detokenizer.push(context, &token)?;
+        let logprobs = context.logprobs(&token, self.params.top_logprobs)?;
+        let generated = LGeneratedToken {
+            token: token.clone(),
+            text,
+            logprob: logprobs.logprob,
+            top_logprobs: logprobs.top_logprobs,
+            position: context.evaluated().len(),
+        };
+
+        self.pending = Some(token);
+        self.generated += 1;
+        Ok(Some(generated))
+    }
+}
+
+impl<'a> Iterator for LGeneratorStream<'a> {
+    type Item = Result<LGeneratedToken, LError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(generated) = self.ready.pop_front() {
+                self.tokens.push(generated.token.clone());
+                self.logprobs.push(LSampleResult {
+                    token: generated.token.clone(),
+                    logprob: generated.logprob,
+                    top_logprobs: generated.top_logprobs.clone(),
+                });
+                return Some(Ok(generated));
+            }
+            if self.finished {
+                return None;
+            }
+            match self.next_token() {
+                Ok(Some(generated)) => self.push_generated(generated),
+                Ok(None) => {
+                    // Nothing more is coming, so anything held back can't be a stop sequence
+                    let leftover = self.detokenizer.flush();
+                    if let Some(last) = self.held.back_mut() {
+                        last.text.push_str(&leftover);
+                    }
+                    self.ready.extend(self.held.drain(..));
+                }
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use crate::generators::LStopSequences;
+
+impl LStopSequences {
+    pub fn new(stop_sequences: &[String]) -> LStopSequences {
+        LStopSequences {
+            stop_sequences: stop_sequences.iter().filter(|stop| !stop.is_empty()).cloned().collect(),
+        }
+    }
+
+    /// The byte offset of the earliest complete stop sequence in text, if there is one.
+    pub fn find(&self, text: &str) -> Option<usize> {
+        self.stop_sequences.iter().filter_map(|stop| text.find(stop.as_str())).min()
+    }
+
+    /// The byte offset from which the end of text could be the start of a stop sequence, or the
+    /// length of text if no stop sequence can begin in it. Text before this offset is safe to release.
+    pub fn partial_start(&self, text: &str) -> usize {
+        self.stop_sequences
+            .iter()
+            .filter_map(|stop| {
+                // The longest proper prefix of stop that text ends with, if any
+                stop.char_indices()
+                    .skip(1)
+                    .map(|(length, _)| length)
+                    .filter(|length| text.ends_with(&stop[..*length]))
+                    .last()
+                    .map(|length| text.len() - length)
+            })
+            .min()
+            .unwrap_or(text.len())
+    }
+}
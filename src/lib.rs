@@ -1,5 +1,13 @@
 pub mod domain;
 pub mod generators;
 
-pub use domain::{LContext, LContextConfig, LError, LSampleParams, LToken, LTokenSequence};
-pub use generators::{LGenerator, LGeneratorParams};
+pub use domain::{
+    LCandidate, LCandidates, LChatMessage, LChatRole, LChatTemplate, LContext, LContextConfig, LDetokenizer, LEmbedOptions, LError, LGrammar,
+    LJsonSchema, LLogitBias, LLogits, LMinP, LModel, LPenalties, LSampleMode, LSampleParams, LSampleResult, LSampler, LSamplerChain, LScoreResult,
+    LSessionState, LTailFree, LTemperature, LTimings, LToken, LTokenSequence, LTokenType, LTokenizeOptions, LTopK, LTopP, LTypical, LVocab,
+    LVocabEntry,
+};
+pub use generators::{LFinishReason, LGeneratedToken, LGenerationResult, LGenerator, LGeneratorParams, LGeneratorStream, LStopSequences};
+
+#[cfg(feature = "tokio")]
+pub use generators::{LAsyncGenerator, LAsyncTokenStream};
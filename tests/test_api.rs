@@ -18,7 +18,7 @@ pub fn main() {
 
     // Load prompt
     let prompt_tokens = context
-        .tokenize("<s>[INST]How would you implement a function that multiplies two matrices together in typescript?[/INST]")
+        .tokenize("[INST]How would you implement a function that multiplies two matrices together in typescript?[/INST]")
         .unwrap();
     let mut token_stream = prompt_tokens;
     assert!(num_predict + token_stream.len() < context_length);
@@ -0,0 +1,184 @@
+use llama_cpp_rs::{LChatMessage, LChatTemplate, LContext, LContextConfig, LError, LGenerator, LGeneratorParams, LSampleParams};
+
+fn conversation() -> Vec<LChatMessage> {
+    vec![
+        LChatMessage::system("You are a pirate."),
+        LChatMessage::user("Hello"),
+        LChatMessage::assistant("Ahoy!"),
+        LChatMessage::user("Where is the treasure?"),
+    ]
+}
+
+#[test]
+pub fn render() {
+    let messages = conversation();
+    assert_eq!(
+        LChatTemplate::Llama2.render(&messages, true).unwrap(),
+        "[INST] <<SYS>>\nYou are a pirate.\n<</SYS>>\n\nHello [/INST] Ahoy! </s><s>[INST] Where is the treasure? [/INST]"
+    );
+    assert_eq!(
+        LChatTemplate::Mistral.render(&messages, true).unwrap(),
+        "[INST] You are a pirate.\n\nHello [/INST]Ahoy!</s>[INST] Where is the treasure? [/INST]"
+    );
+    assert_eq!(
+        LChatTemplate::ChatML.render(&messages, true).unwrap(),
+        "<|im_start|>system\nYou are a pirate.<|im_end|>\n<|im_start|>user\nHello<|im_end|>\n<|im_start|>assistant\nAhoy!<|im_end|>\n\
+         <|im_start|>user\nWhere is the treasure?<|im_end|>\n<|im_start|>assistant\n"
+    );
+    assert_eq!(
+        LChatTemplate::Alpaca.render(&messages, true).unwrap(),
+        "You are a pirate.\n\n### Instruction:\nHello\n\n### Response:\nAhoy!\n\n### Instruction:\nWhere is the treasure?\n\n### Response:\n"
+    );
+    assert_eq!(
+        LChatTemplate::Vicuna.render(&messages, true).unwrap(),
+        "You are a pirate.\n\nUSER: Hello\nASSISTANT: Ahoy!</s>\nUSER: Where is the treasure?\nASSISTANT:"
+    );
+    assert_eq!(
+        LChatTemplate::Zephyr.render(&messages, true).unwrap(),
+        "<|system|>\nYou are a pirate.</s>\n<|user|>\nHello</s>\n<|assistant|>\nAhoy!</s>\n<|user|>\nWhere is the treasure?</s>\n<|assistant|>\n"
+    );
+
+    // Without the generation prompt the conversation ends after the last message
+    assert!(LChatTemplate::ChatML
+        .render(&messages, false)
+        .unwrap()
+        .ends_with("Where is the treasure?<|im_end|>\n"));
+
+    // Tool messages need a template with a tool role
+    let tool = vec![LChatMessage::user("What time is it?"), LChatMessage::tool("12:00")];
+    assert!(LChatTemplate::ChatML
+        .render(&tool, true)
+        .unwrap()
+        .contains("<|im_start|>tool\n12:00<|im_end|>\n"));
+    assert!(matches!(LChatTemplate::Llama2.render(&tool, true), Err(LError::ChatTemplateError(_))));
+}
+
+#[test]
+pub fn detect() {
+    let chatml = "{% for message in messages %}{{'<|im_start|>' + message['role'] + '\n' + message['content'] + '<|im_end|>' + '\n'}}{% endfor %}";
+    assert_eq!(LChatTemplate::detect(chatml), Some(LChatTemplate::ChatML));
+    assert_eq!(
+        LChatTemplate::detect("{{ bos_token + '[INST] <<SYS>>\\n' + system_message + '\\n<</SYS>>\\n\\n' }}"),
+        Some(LChatTemplate::Llama2)
+    );
+    assert_eq!(
+        LChatTemplate::detect("{{ '[INST] ' + message['content'] + ' [/INST]' }}"),
+        Some(LChatTemplate::Mistral)
+    );
+    assert_eq!(
+        LChatTemplate::detect("{{ '<|user|>\n' + message['content'] + eos_token }}"),
+        Some(LChatTemplate::Zephyr)
+    );
+    assert_eq!(LChatTemplate::detect("{{ message['content'] }}"), None);
+}
+
+#[cfg(feature = "jinja")]
+#[test]
+pub fn jinja() {
+    let template = LChatTemplate::Jinja {
+        source: "{{ bos_token }}{% for message in messages %}{% if message['role'] == 'tool' %}{{ raise_exception('no tools') }}{% endif %}\
+                 {{ '<|' + message['role'] + '|>\\n' + message['content'] + eos_token + '\\n' }}{% endfor %}\
+                 {% if add_generation_prompt %}{{ '<|assistant|>\\n' }}{% endif %}"
+            .to_string(),
+        bos_token: "<s>".to_string(),
+        eos_token: "</s>".to_string(),
+    };
+
+    // The leading BOS is left to the tokenizer
+    let messages = conversation();
+    assert_eq!(
+        template.render(&messages, true).unwrap(),
+        LChatTemplate::Zephyr.render(&messages, true).unwrap()
+    );
+    assert!(matches!(
+        template.render(&[LChatMessage::tool("12:00")], true),
+        Err(LError::ChatTemplateError(_))
+    ));
+
+    // Jinja templates stop at the end of turn markers of the built-in template they resemble
+    let chatml = LChatTemplate::Jinja {
+        source: "{% for message in messages %}{{'<|im_start|>' + message['role'] + '\\n' + message['content'] + '<|im_end|>' + '\\n'}}{% endfor %}\
+                 {% if add_generation_prompt %}{{ '<|im_start|>assistant\\n' }}{% endif %}"
+            .to_string(),
+        bos_token: "<s>".to_string(),
+        eos_token: "</s>".to_string(),
+    };
+    assert_eq!(
+        chatml.render(&messages, true).unwrap(),
+        LChatTemplate::ChatML.render(&messages, true).unwrap()
+    );
+    assert_eq!(chatml.stop_sequences(), vec!["<|im_end|>".to_string(), "<|im_start|>".to_string()]);
+    let unknown = LChatTemplate::Jinja {
+        source: "{% for message in messages %}{{ message['content'] }}{% endfor %}".to_string(),
+        bos_token: "<s>".to_string(),
+        eos_token: "</s>".to_string(),
+    };
+    assert!(unknown.stop_sequences().is_empty());
+}
+
+/// The chat template of meta-llama/Llama-2-7b-chat-hf, which uses Python string methods
+const LLAMA2_CHAT_TEMPLATE: &str = "{% if messages[0]['role'] == 'system' %}{% set loop_messages = messages[1:] %}\
+{% set system_message = messages[0]['content'] %}{% else %}{% set loop_messages = messages %}{% set system_message = false %}{% endif %}\
+{% for message in loop_messages %}{% if (message['role'] == 'user') != (loop.index0 % 2 == 0) %}\
+{{ raise_exception('Conversation roles must alternate user/assistant/user/assistant/...') }}{% endif %}\
+{% if loop.index0 == 0 and system_message != false %}\
+{% set content = '<<SYS>>\\n' + system_message + '\\n<</SYS>>\\n\\n' + message['content'] %}\
+{% else %}{% set content = message['content'] %}{% endif %}\
+{% if message['role'] == 'user' %}{{ bos_token + '[INST] ' + content.strip() + ' [/INST]' }}\
+{% elif message['role'] == 'assistant' %}{{ ' '  + content.strip() + ' ' + eos_token }}{% endif %}{% endfor %}";
+
+#[test]
+pub fn from_source() {
+    let template = LChatTemplate::from_source(LLAMA2_CHAT_TEMPLATE, "<s>", "</s>").unwrap();
+    let messages = conversation();
+    assert_eq!(
+        template.render(&messages, true).unwrap(),
+        LChatTemplate::Llama2.render(&messages, true).unwrap()
+    );
+    #[cfg(feature = "jinja")]
+    assert!(matches!(template, LChatTemplate::Jinja { .. }));
+    #[cfg(not(feature = "jinja"))]
+    assert_eq!(template, LChatTemplate::Llama2);
+
+    // Templates that can't be rendered fall back to the built-in template they resemble
+    let unsupported = "{% for message in messages %}{{ '<|im_start|>' + message['role'] + '\\n' }}\
+                       {% generation %}{{ message['content'] }}{% endgeneration %}{{ '<|im_end|>\\n' }}{% endfor %}";
+    assert_eq!(LChatTemplate::from_source(unsupported, "<s>", "</s>"), Some(LChatTemplate::ChatML));
+    assert_eq!(LChatTemplate::from_source("{{ unknown_function() }}", "<s>", "</s>"), None);
+}
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model, and use the template it was trained with
+    let context = LContext::new(config).unwrap();
+    let template = LChatTemplate::from_context(&context).unwrap();
+    println!("{:?}", template);
+
+    let mut generator = LGenerator::new(context);
+    let result = generator
+        .chat(
+            &[
+                LChatMessage::system("You are a helpful assistant. Answer in one sentence."),
+                LChatMessage::user("What is the capital of France?"),
+            ],
+            &template,
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 64,
+                sample_params: LSampleParams {
+                    temp: 0.0f32,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    println!("{} ({:?})", result.text, result.finish_reason);
+    assert!(!result.text.is_empty());
+}
@@ -0,0 +1,29 @@
+use llama_cpp_rs::{LContext, LContextConfig};
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+
+    // Round trip text with multi-byte characters, which are often split into several byte-level tokens
+    let texts = ["Hello world", "  two leading spaces", "Crabs 🦀 and 日本語 text", "line one\nline two"];
+    for text in texts.iter() {
+        let tokens = context.tokenize(text).unwrap();
+        let detokenized = context.detokenize(&tokens).unwrap();
+        println!("{:?} -> {:?} -> {:?}", text, tokens, detokenized);
+        assert_eq!(&detokenized, text);
+
+        // The pieces of the tokens join up to the same bytes
+        let bytes: Vec<u8> = tokens
+            .iter()
+            .filter(|token| token.has_str_value(&context))
+            .flat_map(|token| token.as_bytes(&context).unwrap())
+            .collect();
+        assert!(String::from_utf8_lossy(&bytes).ends_with(text));
+    }
+}
@@ -0,0 +1,29 @@
+use llama_cpp_rs::LDetokenizer;
+
+#[test]
+pub fn main() {
+    // Multi-byte characters split across tokens are only emitted once complete
+    let mut detokenizer = LDetokenizer::new();
+    let crab = "🦀".as_bytes();
+    assert_eq!(detokenizer.push_bytes(b"Hello "), "Hello ");
+    assert_eq!(detokenizer.push_bytes(&crab[0..1]), "");
+    assert_eq!(detokenizer.push_bytes(&crab[1..3]), "");
+    assert!(detokenizer.has_pending());
+    assert_eq!(detokenizer.push_bytes(&crab[3..]), "🦀");
+    assert!(!detokenizer.has_pending());
+
+    // Text before an incomplete character is emitted immediately
+    let kanji = "日本".as_bytes();
+    assert_eq!(detokenizer.push_bytes(&kanji[0..4]), "日");
+    assert_eq!(detokenizer.push_bytes(&kanji[4..]), "本");
+
+    // Bytes that can never be valid are replaced, without holding back the text after them
+    assert_eq!(detokenizer.push_bytes(b"a\xffb"), "a\u{fffd}b");
+    assert_eq!(detokenizer.push_bytes(&[0xe6, b'c']), "\u{fffd}c");
+
+    // Leftovers at the end of the stream are replaced
+    assert_eq!(detokenizer.push_bytes(&crab[0..2]), "");
+    assert_eq!(detokenizer.flush(), "\u{fffd}");
+    assert!(!detokenizer.has_pending());
+    assert_eq!(detokenizer.flush(), "");
+}
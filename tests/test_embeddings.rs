@@ -0,0 +1,55 @@
+use llama_cpp_rs::{LContext, LContextConfig, LEmbedOptions, LError, LModel};
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+#[test]
+pub fn main() {
+    let options = LEmbedOptions {
+        worker_thread_count: 8,
+        normalize: true,
+    };
+
+    // Load model
+    let mut model_config = LContextConfig::new("models/model.gguf");
+    model_config.n_gpu_layers = 32;
+    let model = LModel::new(model_config).unwrap();
+
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 128;
+    config.embedding = true;
+    let mut context = LContext::with_model(&model, config).unwrap();
+
+    // Similar sentences are closer together than unrelated ones
+    let cat = context.embed("The cat sat on the mat.", options).unwrap();
+    let kitten = context.embed("A kitten was sitting on the rug.", options).unwrap();
+    let stocks = context.embed("Stock markets fell sharply on Tuesday.", options).unwrap();
+    assert_eq!(cat.len(), context.n_embd());
+    assert!((dot(&cat, &cat) - 1f32).abs() < 1e-4);
+    println!("cat/kitten {} cat/stocks {}", dot(&cat, &kitten), dot(&cat, &stocks));
+    assert!(dot(&cat, &kitten) > dot(&cat, &stocks));
+
+    // Text longer than the context is pooled over chunks
+    let long_text = "The cat sat on the mat. ".repeat(40);
+    let long = context.embed(&long_text, options).unwrap();
+    assert_eq!(long.len(), context.n_embd());
+    assert!(dot(&cat, &long) > dot(&stocks, &long));
+
+    // Contexts without embeddings report an error
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 128;
+    let mut context = LContext::with_model(&model, config).unwrap();
+    assert!(matches!(
+        context.embed("The cat sat on the mat.", options),
+        Err(LError::EmbeddingsUnavailable(_))
+    ));
+
+    // A context too small to hold BOS and a token of text can't be chunked
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 2;
+    config.embedding = true;
+    let mut context = LContext::with_model(&model, config).unwrap();
+    assert!(matches!(context.embed("The cat sat on the mat.", options), Err(LError::InvalidInput(_))));
+}
@@ -27,6 +27,7 @@ pub fn main() {
                     repeat_penalty: 1.1f32,
                     ..Default::default()
                 },
+                ..Default::default()
             },
         )
         .unwrap();
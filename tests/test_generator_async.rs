@@ -0,0 +1,42 @@
+#![cfg(feature = "tokio")]
+
+use futures::StreamExt;
+use llama_cpp_rs::{LAsyncGenerator, LContext, LContextConfig, LGenerator, LGeneratorParams, LSampleParams};
+use std::io::Write;
+
+#[tokio::test(flavor = "multi_thread")]
+pub async fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+
+    // Stream the first 32 tokens, then drop the stream to stop generating
+    let prompt = "[INST]List some uses for a potato.[/INST]";
+    let generator = LAsyncGenerator::new(LGenerator::new(context));
+    let mut stream = generator
+        .stream(
+            prompt,
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 256,
+                sample_params: LSampleParams { ..Default::default() },
+                ..Default::default()
+            },
+        )
+        .take(32);
+
+    let mut output = String::new();
+    while let Some(generated) = stream.next().await {
+        let generated = generated.unwrap();
+        print!("{}", generated.text);
+        std::io::stdout().flush().unwrap();
+        output.push_str(&generated.text);
+    }
+    assert!(!output.is_empty());
+    println!();
+}
@@ -14,7 +14,7 @@ pub fn main() {
     let context = LContext::new(config).unwrap();
 
     // Run the generator
-    let prompt = "<s>[INST]Replace IMPLEMENT_ME with a real implementation in javascript ```/** Prints o number of times equal to n */\nfunction print_o(int n) { IMPLEMENT_ME };```[/INST]";
+    let prompt = "[INST]Replace IMPLEMENT_ME with a real implementation in javascript ```/** Prints o number of times equal to n */\nfunction print_o(int n) { IMPLEMENT_ME };```[/INST]";
     println!("{}", prompt);
 
     let expected_pattern = Regex::new("(?s).*```.*```.*").unwrap();
@@ -32,6 +32,7 @@ pub fn main() {
                     repeat_penalty: 1f32,
                     ..Default::default()
                 },
+                ..Default::default()
             },
             |generated| {
                 print!("{}", generated[generated.len() - 1]);
@@ -39,6 +39,7 @@ Implement a linked list in rust.
                     repeat_penalty: 1f32,
                     ..Default::default()
                 },
+                ..Default::default()
             },
             |generated| {
                 print!("{}", generated[generated.len() - 1]);
@@ -0,0 +1,98 @@
+use llama_cpp_rs::{LContext, LContextConfig, LFinishReason, LGenerator, LGeneratorParams, LStopSequences};
+
+fn stop_sequences(stops: &[&str]) -> LStopSequences {
+    LStopSequences::new(&stops.iter().map(|stop| stop.to_string()).collect::<Vec<_>>())
+}
+
+#[test]
+pub fn find() {
+    // The earliest complete stop sequence wins
+    let stops = stop_sequences(&["3.", "\n\n"]);
+    assert_eq!(stops.find("1. Apple\n2. Pear\n3. Plum"), Some(17));
+    assert_eq!(stops.find("1. Apple\n\n3. Plum"), Some(8));
+    assert_eq!(stops.find("1. Apple\n2. Pear\n"), None);
+
+    // Offsets are in bytes, after any multibyte text
+    let stops = stop_sequences(&["。"]);
+    assert_eq!(stops.find("日本語。次"), Some(9));
+
+    // Empty stop sequences would match everywhere, so they are ignored
+    assert_eq!(stop_sequences(&[""]).find("anything"), None);
+    assert_eq!(stop_sequences(&["", "ing"]).find("anything"), Some(5));
+}
+
+#[test]
+pub fn partial_start() {
+    // Text that could be the start of a stop sequence is held back
+    let stops = stop_sequences(&["[INST]"]);
+    assert_eq!(stops.partial_start("Hello ["), 6);
+    assert_eq!(stops.partial_start("Hello [IN"), 6);
+    assert_eq!(stops.partial_start("Hello"), 5);
+    assert_eq!(stops.partial_start("Hello [X"), 8);
+
+    // The longest partial match decides, across all the stop sequences
+    let stops = stop_sequences(&["abc", "bcd"]);
+    assert_eq!(stops.partial_start("xab"), 1);
+    assert_eq!(stops.partial_start("xabc"), 2);
+
+    // A stop sequence split across token pieces is held back until it completes or can't
+    let stops = stop_sequences(&["</answer>"]);
+    let mut text = String::new();
+    let mut released = 0;
+    for piece in ["42", " </", "ans", "wer", ">", " more"] {
+        text.push_str(piece);
+        if let Some(stop) = stops.find(&text) {
+            // Everything before the stop sequence had already been released
+            assert_eq!(stop, released);
+            break;
+        }
+        let safe = stops.partial_start(&text);
+        assert!(!text[..safe].contains('<'));
+        released = released.max(safe);
+    }
+    assert_eq!(&text[..released], "42 ");
+
+    // Partial matches of multibyte characters only fall on character boundaries
+    let stops = stop_sequences(&["日本"]);
+    assert_eq!(stops.partial_start("こんにちは日"), 15);
+    assert_eq!(stops.partial_start("こんにちは本"), 18);
+
+    // An empty stop sequence never holds anything back
+    assert_eq!(stop_sequences(&[""]).partial_start("abc"), 3);
+    assert_eq!(stop_sequences(&[]).partial_start(""), 0);
+}
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+
+    // Generate a numbered list, but stop before the third item
+    let prompt = "[INST]Write a numbered list of five fruits.[/INST]";
+    let mut generator = LGenerator::new(context);
+    let result = generator
+        .generate_result(
+            prompt,
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 256,
+                stop_sequences: vec!["3.".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    println!("{}", result.text);
+    println!(
+        "{:?} after {} tokens; prompt {} tokens in {:.0}ms, {:.1} tokens/s",
+        result.finish_reason, result.completion_tokens, result.prompt_tokens, result.prompt_eval_ms, result.tokens_per_second
+    );
+    assert_eq!(result.finish_reason, LFinishReason::StopSequence);
+    assert!(result.text.contains("2."));
+    assert!(!result.text.contains("3."));
+}
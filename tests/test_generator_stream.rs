@@ -0,0 +1,73 @@
+use llama_cpp_rs::{LContext, LContextConfig, LGeneratedToken, LGenerator, LGeneratorParams, LSampleParams, LTokenSequence};
+use std::cell::RefCell;
+use std::io::Write;
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+
+    // Stream tokens until the first full stop
+    let prompt = "[INST]Write a short story about a robot learning to paint.[/INST]";
+    let mut generator = LGenerator::new(context);
+    let output: String = generator
+        .stream(
+            prompt,
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 256,
+                sample_params: LSampleParams { ..Default::default() },
+                ..Default::default()
+            },
+        )
+        .map(|generated| generated.unwrap())
+        .take_while(|generated| !generated.text.contains('.'))
+        .map(|generated| {
+            print!("{}", generated.text);
+            std::io::stdout().flush().unwrap();
+            assert!(generated.logprob <= 0f32);
+            generated.text
+        })
+        .collect();
+    assert!(!output.is_empty());
+    println!();
+
+    // Emoji and CJK characters span several tokens; each is streamed whole, once all its bytes are generated
+    let prompt = "[INST]Reply with three animal emoji, then the Japanese word for cat written in kanji.[/INST]";
+    let params = || LGeneratorParams {
+        worker_thread_count: 8,
+        generate_tokens: 64,
+        sample_params: LSampleParams {
+            temp: 0.0f32,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let generated: Vec<LGeneratedToken> = generator.stream(prompt, params()).map(|generated| generated.unwrap()).collect();
+    let streamed: String = generated.iter().map(|generated| generated.text.as_str()).collect();
+    let mut tokens = LTokenSequence::new();
+    for generated in generated.iter() {
+        tokens.push(generated.token.clone());
+    }
+    println!("{}", streamed);
+    assert!(streamed.chars().any(|c| !c.is_ascii()));
+    assert!(!streamed.contains('\u{FFFD}'));
+    assert_eq!(streamed, generator.context().detokenize(&tokens).unwrap());
+
+    // The text of the streamed tokens adds up to the text of the result
+    let texts = RefCell::new(Vec::new());
+    let result = generator
+        .generate_incremental_result(prompt, params(), |token_strings| {
+            texts.replace(token_strings.to_vec());
+            true
+        })
+        .unwrap();
+    assert_eq!(texts.borrow().concat(), result.text);
+    assert!(!result.text.contains('\u{FFFD}'));
+}
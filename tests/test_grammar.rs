@@ -0,0 +1,78 @@
+use llama_cpp_rs::{LContext, LContextConfig, LError, LGenerator, LGeneratorParams, LGrammar};
+use regex::Regex;
+
+const ANSWER_GRAMMAR: &str = r#"
+# A yes or no answer, followed by a short reason
+root   ::= answer ", because " reason "."
+answer ::= "Yes" | "No"
+reason ::= [a-z ]+
+"#;
+
+#[test]
+pub fn parse() {
+    assert!(LGrammar::parse(ANSWER_GRAMMAR).is_ok());
+    assert!(LGrammar::parse(r#"root ::= ("a" | [b-d])* "\x41" [^\n]?"#).is_ok());
+
+    // Rules must be defined, and there must be a root
+    assert!(matches!(LGrammar::parse("root ::= missing"), Err(LError::GrammarError(_))));
+    assert!(matches!(LGrammar::parse(r#"answer ::= "yes""#), Err(LError::GrammarError(_))));
+
+    // Syntax errors report where they happened
+    match LGrammar::parse("root ::= \"a\"\nother ::= (\"b\"") {
+        Err(LError::GrammarError(message)) => assert!(message.contains("line 2")),
+        _ => panic!("expected a grammar error"),
+    }
+}
+
+#[test]
+pub fn matches() {
+    let grammar = LGrammar::parse(ANSWER_GRAMMAR).unwrap();
+    assert!(grammar.matches("Yes, because it is."));
+    assert!(grammar.matches("No, because it is not."));
+    assert!(!grammar.matches("Maybe, because it is."));
+    assert!(!grammar.matches("Yes, because it is"));
+
+    // Every alternative of the root rule is a starting point
+    let root_alternatives = LGrammar::parse(r#"root ::= "a" | "b" | [0-9]+"#).unwrap();
+    assert!(root_alternatives.matches("a"));
+    assert!(root_alternatives.matches("b"));
+    assert!(root_alternatives.matches("42"));
+    assert!(!root_alternatives.matches("ab"));
+
+    // An empty root matches only empty text
+    let empty = LGrammar::parse(r#"root ::= """#).unwrap();
+    assert!(empty.matches(""));
+    assert!(!empty.matches("a"));
+    let optional = LGrammar::parse(r#"root ::= "a" | "#).unwrap();
+    assert!(optional.matches(""));
+    assert!(optional.matches("a"));
+}
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+
+    // Run the generator
+    let prompt = "[INST]Is the sky blue?[/INST]";
+    let mut generator = LGenerator::new(context);
+    let output = generator
+        .generate(
+            prompt,
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 64,
+                grammar: Some(LGrammar::parse(ANSWER_GRAMMAR).unwrap()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    println!("{}", output);
+    assert!(Regex::new("^(Yes|No), because [a-z ]+").unwrap().is_match(&output));
+}
@@ -0,0 +1,213 @@
+use llama_cpp_rs::{LError, LGrammar, LJsonSchema};
+
+fn person_schema() -> LJsonSchema {
+    LJsonSchema::Object {
+        properties: vec![
+            ("name".to_string(), LJsonSchema::String { pattern: None }),
+            ("age".to_string(), LJsonSchema::Integer),
+            (
+                "role".to_string(),
+                LJsonSchema::Enum(vec!["\"pilot\"".to_string(), "\"potato\"".to_string()]),
+            ),
+            (
+                "tags".to_string(),
+                LJsonSchema::Array {
+                    items: Box::new(LJsonSchema::String { pattern: None }),
+                },
+            ),
+            (
+                "callsign".to_string(),
+                LJsonSchema::String {
+                    pattern: Some("^[A-Z]{2}-\\d{2,4}$".to_string()),
+                },
+            ),
+        ],
+        required: vec!["name".to_string(), "age".to_string(), "role".to_string()],
+    }
+}
+
+#[test]
+pub fn compile() {
+    let gbnf = person_schema().to_gbnf().unwrap();
+    println!("{}", gbnf);
+    assert!(gbnf.starts_with("root ::= "));
+    assert!(LGrammar::parse(&gbnf).is_ok());
+
+    // Objects with only optional properties, and primitive roots
+    let optional = LJsonSchema::Object {
+        properties: vec![("a".to_string(), LJsonSchema::Number), ("b".to_string(), LJsonSchema::Boolean)],
+        required: vec![],
+    };
+    assert!(LGrammar::from_json_schema(&optional).is_ok());
+    assert!(LGrammar::from_json_schema(&LJsonSchema::OneOf(vec![LJsonSchema::Null, LJsonSchema::Integer])).is_ok());
+
+    // Invalid schemas are reported
+    let missing = LJsonSchema::Object {
+        properties: vec![],
+        required: vec!["a".to_string()],
+    };
+    assert!(matches!(LGrammar::from_json_schema(&missing), Err(LError::JsonSchemaError(_))));
+    let bad_pattern = LJsonSchema::String {
+        pattern: Some("(abc".to_string()),
+    };
+    assert!(matches!(LGrammar::from_json_schema(&bad_pattern), Err(LError::JsonSchemaError(_))));
+}
+
+#[test]
+pub fn matches() {
+    let grammar = LGrammar::from_json_schema(&person_schema()).unwrap();
+    assert!(grammar.matches(r#"{"name": "Bob", "age": 32, "role": "pilot", "tags": ["a \"quoted\" tag", "C:\\"], "callsign": "BZ-123"}"#));
+    assert!(!grammar.matches(r#"{"name": "Bob", "age": 32}"#));
+    assert!(!grammar.matches(r#"{"name": "Bob", "age": 32, "role": "pilot", "callsign": "BZ-1"}"#));
+
+    // Control characters, quotes and backslashes in strings must be escaped
+    let string = LGrammar::from_json_schema(&LJsonSchema::String { pattern: None }).unwrap();
+    assert!(string.matches(r#""line\nbreak\u0001""#));
+    assert!(!string.matches("\"line\nbreak\""));
+    assert!(!string.matches("\"bell\x07\""));
+    assert!(!string.matches(r#""a"b""#));
+    assert!(!string.matches(r#""a\b\""#));
+
+    let pattern = |pattern: &str| {
+        LGrammar::from_json_schema(&LJsonSchema::String {
+            pattern: Some(pattern.to_string()),
+        })
+        .unwrap()
+    };
+
+    // Pattern literals are emitted in their escaped form
+    let literal = pattern(r#"^say "hi"\\$"#);
+    assert!(literal.matches(r#""say \"hi\"\\""#));
+    assert!(!literal.matches(r#""say "hi"\""#));
+
+    // Negated classes and '.' never match a character that needs escaping
+    let negated = pattern("^[^a]+$");
+    assert!(negated.matches(r#""bcd""#));
+    assert!(!negated.matches(r#""b"c""#));
+    assert!(!negated.matches(r#""b\"c""#));
+    assert!(!negated.matches("\"b\nc\""));
+    let any = pattern("^.+$");
+    assert!(any.matches(r#""x y""#));
+    assert!(!any.matches(r#""x\y""#));
+    assert!(!any.matches("\"x\ty\""));
+
+    // Classes that name those characters match their escape sequences instead
+    let class = pattern(r#"^[a"\\\n]+$"#);
+    assert!(class.matches(r#""a\"\\\n""#));
+    assert!(!class.matches(r#""a"""#));
+    assert!(!class.matches("\"a\n\""));
+    let range = pattern("^[ -~]+$");
+    assert!(range.matches(r#""x \"y\" \\ z""#));
+    assert!(!range.matches(r#""x "y" z""#));
+
+    // Negated shorthands match everything except their class, but only characters that need no escaping
+    let not_digits = pattern(r"^\D+$");
+    assert!(not_digits.matches(r#""D-x y""#));
+    assert!(!not_digits.matches(r#""a1""#));
+    assert!(!not_digits.matches(r#""a\"b""#));
+    let not_word = pattern(r"^\W$");
+    assert!(not_word.matches(r#""-""#));
+    assert!(!not_word.matches(r#""_""#));
+    let not_space = pattern(r"^\S+$");
+    assert!(not_space.matches(r#""a-b""#));
+    assert!(!not_space.matches(r#""a b""#));
+    assert!(!not_space.matches(r#""a\tb""#));
+
+    // \s matches tabs, newlines and Unicode spaces as well as the space character
+    let space = pattern(r"^a\sb$");
+    assert!(space.matches(r#""a b""#));
+    assert!(space.matches(r#""a\tb""#));
+    assert!(space.matches(r#""a\nb""#));
+    assert!(space.matches("\"a\u{3000}b\""));
+    assert!(!space.matches(r#""a-b""#));
+
+    // Shorthands and escapes inside classes
+    let class = pattern(r"^[\D\x41]+$");
+    assert!(class.matches(r#""xA-""#));
+    assert!(!class.matches(r#""x1""#));
+    let digits = pattern(r"^[^\D]+$");
+    assert!(digits.matches(r#""12""#));
+    assert!(!digits.matches(r#""1a""#));
+    let escaped = pattern(r"^\u00e9\.\x2a$");
+    assert!(escaped.matches(r#""é.*""#));
+    assert!(!escaped.matches(r#""éa*""#));
+
+    // Unsupported escapes are reported instead of being matched as letters
+    for unsupported in [r"^\bword\b$", r"^\p{L}+$", r"^(a)\1$", r"^[\B]$", r"^\x4$"] {
+        let result = LGrammar::from_json_schema(&LJsonSchema::String {
+            pattern: Some(unsupported.to_string()),
+        });
+        assert!(matches!(result, Err(LError::JsonSchemaError(_))), "{}", unsupported);
+    }
+
+    // Any one of the schemas can match at the root
+    let one_of = LGrammar::from_json_schema(&LJsonSchema::OneOf(vec![LJsonSchema::Null, LJsonSchema::Integer])).unwrap();
+    assert!(one_of.matches("null"));
+    assert!(one_of.matches("42"));
+    assert!(!one_of.matches("true"));
+    #[cfg(feature = "serde")]
+    {
+        let any_of = LGrammar::from_json_schema(&LJsonSchema::parse(r#"{"anyOf": [{"type": "string"}, {"type": "boolean"}]}"#).unwrap()).unwrap();
+        assert!(any_of.matches(r#""yes""#));
+        assert!(any_of.matches("false"));
+        assert!(!any_of.matches("1"));
+        let nullable = LGrammar::from_json_schema(&LJsonSchema::parse(r#"{"type": ["integer", "null"]}"#).unwrap()).unwrap();
+        assert!(nullable.matches("-7"));
+        assert!(nullable.matches("null"));
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn main() {
+    use llama_cpp_rs::{LContext, LContextConfig, LGenerator, LGeneratorParams};
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug)]
+    struct Person {
+        name: String,
+        age: i64,
+        role: String,
+    }
+
+    let schema = LJsonSchema::parse(
+        r#"{
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "age": { "type": "integer" },
+                "role": { "enum": ["pilot", "potato"] }
+            },
+            "required": ["name", "age", "role"]
+        }"#,
+    )
+    .unwrap();
+
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+
+    // Run the generator
+    let prompt = "[INST]Bob is a 32 year old space pilot. Describe bob as JSON.[/INST]";
+    let mut generator = LGenerator::new(context);
+    let person: Person = generator
+        .generate_json(
+            prompt,
+            &schema,
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 128,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    println!("{:?}", person);
+    assert!(person.role == "pilot" || person.role == "potato");
+    assert!(!person.name.is_empty());
+    assert!(person.age >= 0);
+}
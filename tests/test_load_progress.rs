@@ -0,0 +1,21 @@
+use llama_cpp_rs::{LContext, LContextConfig, LError};
+use std::io::Write;
+
+#[test]
+pub fn main() {
+    // Report progress while loading
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_gpu_layers = 32;
+    config.set_progress_callback(|progress| {
+        print!("\rloading... {:.0}%", progress * 100f32);
+        std::io::stdout().flush().unwrap();
+        true
+    });
+    assert!(LContext::new(config).is_ok());
+    println!();
+
+    // Cancel part way through the load
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.set_progress_callback(|progress| progress < 0.5f32);
+    assert!(matches!(LContext::new(config), Err(LError::LoadCancelled(_))));
+}
@@ -0,0 +1,91 @@
+use llama_cpp_rs::{LContext, LContextConfig, LError, LFinishReason, LGenerator, LGeneratorParams, LGrammar, LLogitBias, LToken, LTokenizeOptions};
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+    let mut generator = LGenerator::new(context);
+
+    // Never mention the capital by name, and keep going until the token limit
+    let mut logit_bias = LLogitBias::new();
+    logit_bias.ban_text(generator.context(), "Paris").unwrap();
+    logit_bias.ban_eos = true;
+
+    // Every token of the banned text is banned on its own
+    let banned = generator
+        .context()
+        .tokenize_with(
+            "Paris",
+            LTokenizeOptions {
+                add_bos: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!banned.is_empty());
+    assert!(banned.iter().all(|token| logit_bias.get(&token) == Some(f32::NEG_INFINITY)));
+
+    let prompt = "[INST]What is the capital of France?[/INST]";
+    let result = generator
+        .generate_result(
+            prompt,
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 48,
+                logit_bias: Some(logit_bias),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    println!("{}", result.text);
+    assert_eq!(result.finish_reason, LFinishReason::TokenLimit);
+    assert_eq!(result.completion_tokens, 48);
+    assert!(!result.tokens.iter().any(|token| banned.iter().any(|banned| banned == token)));
+
+    // A grammar decides when the output is complete, so ban_eos does not stop it from ending
+    let grammar = LGrammar::parse(r#"root ::= "Yes" | "No""#).unwrap();
+    let mut logit_bias = LLogitBias::new();
+    logit_bias.ban_eos = true;
+    let result = generator
+        .generate_result(
+            "[INST]Is Paris the capital of France? Answer yes or no.[/INST]",
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 16,
+                grammar: Some(grammar.clone()),
+                logit_bias: Some(logit_bias),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    println!("{}", result.text);
+    assert_eq!(result.finish_reason, LFinishReason::EndOfStream);
+    assert!(result.text == "Yes" || result.text == "No");
+
+    // Banning every token the grammar allows is an error, not an abort
+    let mut logit_bias = LLogitBias::new();
+    for id in 0..generator.context().vocab().len() {
+        let token = LToken::from(id as i32);
+        let piece = token.as_bytes(generator.context()).unwrap_or_default();
+        if piece.starts_with(b"Y") || piece.starts_with(b"N") {
+            logit_bias.ban(&token);
+        }
+    }
+    let result = generator.generate_result(
+        "[INST]Is Paris the capital of France? Answer yes or no.[/INST]",
+        LGeneratorParams {
+            worker_thread_count: 8,
+            generate_tokens: 16,
+            grammar: Some(grammar),
+            logit_bias: Some(logit_bias),
+            ..Default::default()
+        },
+    );
+    assert!(matches!(result, Err(LError::NoValidTokens(_))));
+}
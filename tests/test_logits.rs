@@ -0,0 +1,53 @@
+use llama_cpp_rs::{LContext, LContextConfig, LError, LTokenSequence};
+
+#[test]
+pub fn main() {
+    let sample_worker_threads = 8;
+
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.n_gpu_layers = 32;
+    config.logits_all = true;
+
+    // Load model
+    let mut context = LContext::new(config).unwrap();
+
+    // Logits are only available after a step
+    assert!(matches!(context.logits(), Err(LError::CannotSampleBeforeInference)));
+
+    // Classify the sentiment of a review by comparing the logits of the two possible answers
+    let prompt = context
+        .tokenize("[INST]Is this review positive or negative? 'An absolute delight from start to finish.'[/INST] The review is")
+        .unwrap();
+    let positive = context.vocab().find_piece(b" positive").unwrap();
+    let negative = context.vocab().find_piece(b" negative").unwrap();
+    assert_ne!(positive.id(), negative.id());
+    context.load_prompt(&prompt, sample_worker_threads).unwrap();
+
+    let logits = context.logits().unwrap();
+    assert_eq!(logits.len(), context.n_vocab());
+    let positive_logit = logits[positive.id()];
+    let negative_logit = logits[negative.id()];
+    println!("positive {} negative {}", positive_logit, negative_logit);
+    assert!(positive_logit > negative_logit);
+
+    // Every position of the prompt has logits, predicting the token after it
+    let all_logits = context.all_logits().unwrap();
+    assert_eq!(all_logits.len(), prompt.len());
+    assert_eq!(all_logits.start(), 0);
+    assert_eq!(all_logits.get(prompt.len() - 1).unwrap(), context.logits().unwrap());
+    assert_eq!(context.logits_for(prompt.len() - 1).unwrap(), context.logits().unwrap());
+    for (position, row) in all_logits.rows().enumerate() {
+        assert_eq!(row, context.logits_for(position).unwrap());
+    }
+    assert_ne!(all_logits.get(0).unwrap(), all_logits.get(1).unwrap());
+
+    // After the next step only the new token is available
+    let mut next = LTokenSequence::new();
+    next.push(positive);
+    context.step(&next, sample_worker_threads).unwrap();
+    assert_eq!(context.all_logits().unwrap().len(), 1);
+    assert!(context.logits_for(prompt.len()).is_ok());
+    assert!(matches!(context.logits_for(0), Err(LError::LogitsUnavailable(_))));
+}
@@ -0,0 +1,43 @@
+use llama_cpp_rs::{LContext, LContextConfig, LGenerator, LGeneratorParams};
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+
+    // Report the three most likely alternatives for each token
+    let prompt = "[INST]What is the capital of France?[/INST]";
+    let mut generator = LGenerator::new(context);
+    let result = generator
+        .generate_result(
+            prompt,
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 16,
+                top_logprobs: 3,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    println!("{}", result.text);
+
+    assert_eq!(result.logprobs.len(), result.completion_tokens);
+    for (token, logprobs) in result.tokens.iter().zip(result.logprobs.iter()) {
+        println!("{:?} {:.3} {:?}", logprobs.token, logprobs.logprob, logprobs.top_logprobs);
+        assert_eq!(token, logprobs.token);
+        assert!(logprobs.logprob <= 0f32);
+        assert_eq!(logprobs.top_logprobs.len(), 3);
+
+        // Alternatives are the most likely first, and none is less likely than a token outside the top three
+        assert!(logprobs.top_logprobs.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        if !logprobs.top_logprobs.iter().any(|(alternative, _)| *alternative == logprobs.token) {
+            assert!(logprobs.logprob <= logprobs.top_logprobs[2].1);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use llama_cpp_rs::LCandidates;
+
+fn kept_ids(candidates: &LCandidates) -> Vec<i32> {
+    candidates.iter().map(|candidate| candidate.id).collect()
+}
+
+#[test]
+pub fn main() {
+    // Probabilities proportional to 8, 4, 2, 1 and 1/8 relative to the least likely token
+    let ln2 = 2f32.ln();
+    let logits = [ln2, 3f32 * ln2, -3f32 * ln2, 2f32 * ln2, 0f32];
+
+    // Keep tokens with at least a fifth of the probability of the most likely token
+    let mut candidates = LCandidates::from_logits(&logits);
+    candidates.min_p(0.2, 1);
+    assert!(candidates.is_sorted());
+    assert_eq!(kept_ids(&candidates), vec![1, 3, 0]);
+
+    // Probabilities are normalised over all the tokens, before filtering
+    let total = 8f32 + 4f32 + 2f32 + 1f32 + 0.125f32;
+    assert!((candidates.as_slice()[0].p - 8f32 / total).abs() < 1e-6);
+
+    // Disabled
+    let mut candidates = LCandidates::from_logits(&logits);
+    candidates.min_p(0.0, 1);
+    assert_eq!(candidates.len(), logits.len());
+    assert!(!candidates.is_sorted());
+
+    // Only the most likely token passes, but min_keep is respected
+    let mut candidates = LCandidates::from_logits(&logits);
+    candidates.min_p(1.0, 1);
+    assert_eq!(kept_ids(&candidates), vec![1]);
+    let mut candidates = LCandidates::from_logits(&logits);
+    candidates.min_p(1.0, 2);
+    assert_eq!(kept_ids(&candidates), vec![1, 3]);
+
+    // High temperature sampling flattens the logits, but min_p still removes the tail
+    let flattened: Vec<f32> = logits.iter().map(|logit| logit / 2f32).collect();
+    let mut candidates = LCandidates::from_logits(&flattened);
+    candidates.min_p(0.2, 1);
+    assert_eq!(kept_ids(&candidates), vec![1, 3, 0, 4]);
+
+    // Nothing to filter
+    let mut candidates = LCandidates::from_logits(&[]);
+    candidates.min_p(0.5, 1);
+    assert!(candidates.is_empty());
+}
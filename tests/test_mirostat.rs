@@ -0,0 +1,70 @@
+use llama_cpp_rs::{LContext, LContextConfig, LGenerator, LGeneratorParams, LSampleMode, LSampleParams, LTokenSequence};
+
+fn mirostat_params(mode: LSampleMode) -> LSampleParams {
+    LSampleParams {
+        temp: 0.8f32,
+        mode,
+        ..Default::default()
+    }
+}
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let mut context = LContext::new(config).unwrap();
+    let prompt = context.tokenize("[INST]Tell me a short story about a dragon.[/INST]").unwrap();
+    let modes = [
+        LSampleMode::Mirostat {
+            tau: 5.0f32,
+            eta: 0.1f32,
+            m: 100,
+        },
+        LSampleMode::MirostatV2 { tau: 5.0f32, eta: 0.1f32 },
+    ];
+
+    // Mu starts at 2 * tau on the first sample and is updated by every sample after it
+    for mode in modes {
+        context.load_prompt(&prompt, 8).unwrap();
+        assert_eq!(context.mirostat_mu(), None);
+        let mut mu = Vec::new();
+        let mut next = LTokenSequence::new();
+        for _ in 0..16 {
+            let token = context.sample(Some(mirostat_params(mode))).unwrap();
+            mu.push(context.mirostat_mu().unwrap());
+            next.clear();
+            next.push(token);
+            context.step(&next, 8).unwrap();
+        }
+        println!("{:?}: {:?}", mode, mu);
+        assert!(mu.iter().all(|mu| mu.is_finite()));
+        assert!(mu.windows(2).any(|pair| pair[0] != pair[1]));
+
+        // Loading a new prompt starts the estimate again
+        context.load_prompt(&prompt, 8).unwrap();
+        assert_eq!(context.mirostat_mu(), None);
+    }
+
+    // Generate text with each mode
+    let mut generator = LGenerator::new(context);
+    for mode in modes {
+        let output = generator
+            .generate(
+                "[INST]Tell me a short story about a dragon.[/INST]",
+                LGeneratorParams {
+                    worker_thread_count: 8,
+                    generate_tokens: 64,
+                    sample_params: mirostat_params(mode),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        println!("{:?}: {}", mode, output);
+        assert!(!output.is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+use llama_cpp_rs::{LContextConfig, LError, LModel};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A path in a temporary directory of its own, so tests running at the same time don't collide.
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("llama-cpp-rs-test-model-file-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn load(path: &Path) -> Result<LModel, LError> {
+    LModel::new(LContextConfig::new(path))
+}
+
+#[test]
+pub fn main() {
+    // No file at all
+    let missing = temp_path("missing.gguf");
+    assert!(matches!(load(&missing), Err(LError::ModelFileMissing(path)) if path == missing));
+
+    // A directory is not a model file
+    let directory = temp_path("directory.gguf");
+    fs::create_dir_all(&directory).unwrap();
+    assert!(matches!(load(&directory), Err(LError::ModelFileUnreadable(path, _)) if path == directory));
+
+    // A file too short to hold the magic number
+    let short = temp_path("short.gguf");
+    fs::write(&short, b"GG").unwrap();
+    assert!(matches!(load(&short), Err(LError::UnsupportedModelFormat(path, _)) if path == short));
+
+    // Models in the formats that came before GGUF need converting
+    for (name, magic) in [("ggml.bin", 0x67676d6cu32), ("ggmf.bin", 0x67676d66u32), ("ggjt.bin", 0x67676a74u32)] {
+        let legacy = temp_path(name);
+        let mut bytes = magic.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[0u8; 64]);
+        fs::write(&legacy, &bytes).unwrap();
+        match load(&legacy) {
+            Err(LError::UnsupportedModelFormat(path, message)) => {
+                assert_eq!(path, legacy);
+                assert!(message.contains("legacy GGML"), "{}", message);
+            }
+            Err(err) => panic!("expected UnsupportedModelFormat, got {:?}", err),
+            Ok(_) => panic!("expected UnsupportedModelFormat for {}", name),
+        }
+    }
+
+    // Anything else without the GGUF header
+    let text = temp_path("text.gguf");
+    fs::write(&text, b"this is not a model").unwrap();
+    match load(&text) {
+        Err(LError::UnsupportedModelFormat(path, message)) => {
+            assert_eq!(path, text);
+            assert!(message.contains("missing GGUF header"), "{}", message);
+        }
+        Err(err) => panic!("expected UnsupportedModelFormat, got {:?}", err),
+        Ok(_) => panic!("expected UnsupportedModelFormat"),
+    }
+
+    fs::remove_dir_all(missing.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,117 @@
+use llama_cpp_rs::{
+    LCandidates, LContext, LContextConfig, LError, LGenerator, LGeneratorParams, LPenalties, LSampleParams, LSampler, LSamplerChain, LToken,
+    LTokenSequence,
+};
+
+/// The id of a single token in the vocabulary.
+fn token_id(context: &LContext, piece: &str) -> i32 {
+    context.vocab().find_piece(piece.as_bytes()).unwrap().id() as i32
+}
+
+/// Every candidate with the same logit, so the effect of the penalties can be read off directly.
+fn flat_candidates(context: &LContext) -> LCandidates {
+    LCandidates::from_logits(&vec![1f32; context.n_vocab()])
+}
+
+fn logit(candidates: &LCandidates, id: i32) -> f32 {
+    candidates.iter().find(|candidate| candidate.id == id).unwrap().logit
+}
+
+#[test]
+pub fn synthetic() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.n_gpu_layers = 32;
+    let mut context = LContext::new(config).unwrap();
+    let prompt = context.tokenize("[INST]Name a fruit.[/INST]").unwrap();
+    context.load_prompt(&prompt, 8).unwrap();
+
+    // Build a known history by forcing the sampler to choose each token in turn
+    let apple = token_id(&context, " apple");
+    let pear = token_id(&context, " pear");
+    let plum = token_id(&context, " plum");
+    let unseen = token_id(&context, " kiwi");
+    for id in [apple, apple, pear, plum] {
+        let mut forced = LSamplerChain::new().with(move |_: &LContext, candidates: &mut LCandidates| -> Result<(), LError> {
+            candidates.retain(|candidate| candidate.id == id);
+            Ok(())
+        });
+        assert_eq!(context.sample_with_chain(&mut forced).unwrap().id() as i32, id);
+    }
+    let mut exempt = LTokenSequence::new();
+    exempt.push(LToken::from(plum));
+    context.set_penalty_exempt_tokens(&exempt);
+
+    // Repeated tokens are penalized more the more often they appear; exempt and unseen tokens are left alone
+    let mut penalties = LPenalties {
+        repeat_penalty: 1.5,
+        frequency_penalty: 0.5,
+        presence_penalty: 0.5,
+        history_length: 64,
+        penalize_newline: false,
+    };
+    let mut candidates = flat_candidates(&context);
+    penalties.apply(&context, &mut candidates).unwrap();
+    println!("apple {} pear {}", logit(&candidates, apple), logit(&candidates, pear));
+    assert!(logit(&candidates, pear) < 1f32);
+    assert!(logit(&candidates, apple) < logit(&candidates, pear));
+    assert_eq!(logit(&candidates, plum), 1f32);
+    assert_eq!(logit(&candidates, unseen), 1f32);
+
+    // Only the most recent history_length tokens count
+    penalties.history_length = 2;
+    let mut candidates = flat_candidates(&context);
+    penalties.apply(&context, &mut candidates).unwrap();
+    assert!(logit(&candidates, pear) < 1f32);
+    assert_eq!(logit(&candidates, apple), 1f32);
+
+    // Neutral penalties change nothing
+    let mut neutral = LPenalties {
+        repeat_penalty: 1.0,
+        frequency_penalty: 0.0,
+        presence_penalty: 0.0,
+        history_length: 64,
+        penalize_newline: true,
+    };
+    let mut candidates = flat_candidates(&context);
+    neutral.apply(&context, &mut candidates).unwrap();
+    assert!(candidates.iter().all(|candidate| candidate.logit == 1f32));
+}
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+    let mut generator = LGenerator::new(context);
+
+    // List formatting is expected to repeat, so exempt it from the penalties
+    let penalty_exempt_tokens = generator.context().tokenize("-,.").unwrap();
+
+    let prompt = "[INST]Write a list of ten different animals, one per line.[/INST]";
+    let output = generator
+        .generate(
+            prompt,
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 128,
+                sample_params: LSampleParams {
+                    frequency_penalty: 0.5,
+                    presence_penalty: 0.5,
+                    penalize_newline: false,
+                    ..Default::default()
+                },
+                penalty_exempt_tokens,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    println!("{}", output);
+    assert!(output.lines().count() > 1);
+}
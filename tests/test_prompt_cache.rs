@@ -0,0 +1,67 @@
+use llama_cpp_rs::{LContext, LContextConfig, LGenerationResult, LGenerator, LGeneratorParams, LModel, LSampleParams};
+
+fn generate(generator: &mut LGenerator, prompt: &str) -> LGenerationResult {
+    generator
+        .generate_result(
+            prompt,
+            LGeneratorParams {
+                worker_thread_count: 8,
+                generate_tokens: 32,
+                sample_params: LSampleParams {
+                    top_k: 1,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap()
+}
+
+fn token_ids(result: &LGenerationResult) -> Vec<usize> {
+    result.tokens.iter().map(|token| token.id()).collect()
+}
+
+#[test]
+pub fn main() {
+    // Load the weights once, for a generator that is reused and one that always starts fresh
+    let mut model_config = LContextConfig::new("models/model.gguf");
+    model_config.n_gpu_layers = 32;
+    let model = LModel::new(model_config).unwrap();
+    let new_generator = || {
+        let mut config = LContextConfig::new(model.path());
+        config.n_ctx = 512;
+        config.seed = 1;
+        LGenerator::new(LContext::with_model(&model, config).unwrap())
+    };
+
+    // Two prompts that share a system prompt
+    let system = "[INST]<<SYS>>\nYou are a helpful assistant who answers in one short sentence.\n<</SYS>>\n\n";
+    let first_prompt = format!("{}What is the capital of France?[/INST]", system);
+    let second_prompt = format!("{}What is the capital of Japan?[/INST]", system);
+
+    // The first generation evaluates the whole prompt
+    let mut generator = new_generator();
+    let first = generate(&mut generator, &first_prompt);
+    println!("{}", first.text);
+    assert_eq!(first.cached_prompt_tokens, 0);
+
+    // The second reuses the shared prefix from the KV cache
+    let second = generate(&mut generator, &second_prompt);
+    println!(
+        "{} ({} of {} prompt tokens cached)",
+        second.text, second.cached_prompt_tokens, second.prompt_tokens
+    );
+    assert!(second.cached_prompt_tokens > 0);
+    assert!(second.cached_prompt_tokens < second.prompt_tokens);
+
+    // Reusing the cache gives the same output as evaluating the prompt in a fresh context
+    let fresh = generate(&mut new_generator(), &second_prompt);
+    assert_eq!(fresh.cached_prompt_tokens, 0);
+    assert_eq!(second.text, fresh.text);
+    assert_eq!(token_ids(&second), token_ids(&fresh));
+
+    // Repeating a prompt reuses all of it except the last token, whose logits are needed to sample
+    let repeated = generate(&mut generator, &second_prompt);
+    assert_eq!(repeated.cached_prompt_tokens, repeated.prompt_tokens - 1);
+    assert_eq!(repeated.text, fresh.text);
+}
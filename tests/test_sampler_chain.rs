@@ -0,0 +1,57 @@
+use llama_cpp_rs::{LCandidates, LContext, LContextConfig, LError, LGenerator, LGeneratorParams, LPenalties, LSamplerChain, LTemperature, LTopK};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Greedy sampling, with a custom sampler that counts how often it is called.
+fn greedy_chain(calls: Arc<AtomicUsize>) -> LSamplerChain {
+    LSamplerChain::new()
+        .with(LPenalties {
+            repeat_penalty: 1.1,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            history_length: 64,
+            penalize_newline: false,
+        })
+        .with(move |_: &LContext, candidates: &mut LCandidates| -> Result<(), LError> {
+            calls.fetch_add(1, Ordering::SeqCst);
+            assert!(!candidates.is_empty());
+            Ok(())
+        })
+        .with(LTemperature(0.7))
+        .with(LTopK(1))
+}
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.seed = 1;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+    let mut generator = LGenerator::new(context);
+
+    // Greedy sampling always produces the same output
+    let prompt = "[INST]Name three colours.[/INST]";
+    let mut outputs = Vec::new();
+    for _ in 0..2 {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let result = generator
+            .generate_result(
+                prompt,
+                LGeneratorParams {
+                    worker_thread_count: 8,
+                    generate_tokens: 32,
+                    sampler: Some(greedy_chain(calls.clone())),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        println!("{}", result.text);
+        assert!(calls.load(Ordering::SeqCst) >= result.completion_tokens);
+        outputs.push(result.text);
+    }
+    assert_eq!(outputs[0], outputs[1]);
+}
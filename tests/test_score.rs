@@ -0,0 +1,55 @@
+use llama_cpp_rs::{LContext, LContextConfig, LError};
+
+#[test]
+pub fn main() {
+    let sample_worker_threads = 8;
+
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 256;
+    config.n_batch = 64;
+    config.n_gpu_layers = 32;
+    config.logits_all = true;
+
+    // Load model
+    let mut context = LContext::new(config).unwrap();
+
+    // Rank candidate completions by likelihood
+    let candidates = ["The capital of France is Paris.", "The capital of France is a small potato."];
+    let mut scores = Vec::new();
+    for candidate in candidates.iter() {
+        let tokens = context.tokenize(candidate).unwrap();
+        let score = context.score(&tokens, sample_worker_threads).unwrap();
+        println!("{:.3} {:.3} {}", score.logprob, score.perplexity, candidate);
+        assert_eq!(score.token_logprobs.len(), tokens.len() - 1);
+        assert!(score.token_logprobs.iter().all(|logprob| *logprob <= 0f32));
+        scores.push(score);
+    }
+    assert!(scores[0].logprob > scores[1].logprob);
+    assert!(scores[0].perplexity < scores[1].perplexity);
+
+    // Perplexity over a text several contexts long; repetitive text is very predictable
+    let text = "One, two, three, four, five, six, seven, eight, nine, ten. ".repeat(64);
+    let result = context.perplexity(&text, sample_worker_threads).unwrap();
+    println!("perplexity {:.3} over {} tokens", result.perplexity, result.token_logprobs.len());
+    assert!(result.token_logprobs.len() > 256);
+    assert!(result.perplexity < 2.0);
+
+    // Input longer than n_batch is evaluated in several batches, and only the last keeps its logits
+    let long = context.tokenize(&"The quick brown fox jumps over the lazy dog. ".repeat(12)).unwrap();
+    assert!(long.len() > 64);
+    context.load_prompt(&long, sample_worker_threads).unwrap();
+    assert_eq!(context.evaluated().len(), long.len());
+    assert!(context.logits_for(long.len() - 1).is_ok());
+    assert!(matches!(context.logits_for(0), Err(LError::LogitsUnavailable(_))));
+    let score = context.score(&long, sample_worker_threads).unwrap();
+    assert_eq!(score.token_logprobs.len(), long.len() - 1);
+
+    // Input too short to score is rejected
+    let bos = context.tokenize("").unwrap();
+    assert!(matches!(context.score(&bos, sample_worker_threads), Err(LError::InvalidInput(_))));
+    assert!(matches!(
+        context.perplexity("Too short.", sample_worker_threads),
+        Err(LError::InvalidInput(_))
+    ));
+}
@@ -0,0 +1,51 @@
+use llama_cpp_rs::{LContext, LContextConfig, LSampleParams};
+
+#[test]
+pub fn main() {
+    let sample_worker_threads = 8;
+    let session_path = std::env::temp_dir().join("llama-cpp-rs-test-session.bin");
+
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.n_gpu_layers = 32;
+
+    // Evaluate a prompt and save the result
+    let mut context = LContext::new(config).unwrap();
+    let prompt_tokens = context.tokenize("[INST]Name three colors.[/INST]").unwrap();
+    context.load_prompt(&prompt_tokens, sample_worker_threads).unwrap();
+    context.save_session(&session_path, &prompt_tokens).unwrap();
+
+    // Take a snapshot, sample, and restore the snapshot
+    let snapshot = context.snapshot();
+    let params = LSampleParams {
+        top_k: 1,
+        ..Default::default()
+    };
+    let first = context.sample(Some(params)).unwrap().as_string(&mut context).unwrap();
+    context.restore(&snapshot).unwrap();
+    let second = context.sample(Some(params)).unwrap().as_string(&mut context).unwrap();
+    assert_eq!(first, second);
+
+    // Load the session into a fresh context for the same model
+    let mut config = LContextConfig::new(context.model().path());
+    config.n_ctx = 512;
+    let mut restored = LContext::with_model(context.model(), config).unwrap();
+    let restored_tokens = restored.load_session(&session_path, sample_worker_threads).unwrap();
+    assert_eq!(restored_tokens.len(), prompt_tokens.len());
+    let third = restored.sample(Some(params)).unwrap().as_string(&mut restored).unwrap();
+    assert_eq!(first, third);
+
+    // Contexts that keep the logits of every token can also sample straight after loading a session
+    let mut config = LContextConfig::new(context.model().path());
+    config.n_ctx = 512;
+    config.logits_all = true;
+    let mut restored_all = LContext::with_model(context.model(), config).unwrap();
+    restored_all.load_session(&session_path, sample_worker_threads).unwrap();
+    assert_eq!(restored_all.logits().unwrap().len(), restored_all.n_vocab());
+    assert!(restored_all.logits_for(prompt_tokens.len() - 1).is_ok());
+    let fourth = restored_all.sample(Some(params)).unwrap().as_string(&mut restored_all).unwrap();
+    assert_eq!(first, fourth);
+
+    std::fs::remove_file(&session_path).unwrap();
+}
@@ -0,0 +1,68 @@
+use llama_cpp_rs::{LContext, LContextConfig, LGenerator, LGeneratorParams, LModel, LSampleParams};
+use std::thread;
+
+#[test]
+pub fn main() {
+    // Load the weights once
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_gpu_layers = 32;
+    let model = LModel::new(config).unwrap();
+
+    // Run several independent sessions against the same model
+    let prompts = [
+        "[INST]Describe a cat in one sentence.[/INST]",
+        "[INST]Describe a dog in one sentence.[/INST]",
+    ];
+    let workers: Vec<_> = prompts
+        .iter()
+        .enumerate()
+        .map(|(i, prompt)| {
+            let model = model.clone();
+            let prompt = prompt.to_string();
+            thread::spawn(move || {
+                let mut config = LContextConfig::new(model.path());
+                config.n_ctx = 256;
+                config.seed = i as u32;
+                let context = LContext::with_model(&model, config).unwrap();
+
+                let mut generator = LGenerator::new(context);
+                generator
+                    .generate(
+                        &prompt,
+                        LGeneratorParams {
+                            worker_thread_count: 4,
+                            generate_tokens: 64,
+                            sample_params: LSampleParams { ..Default::default() },
+                            ..Default::default()
+                        },
+                    )
+                    .unwrap()
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        let output = worker.join().unwrap();
+        assert!(!output.is_empty());
+        println!("{}", output);
+    }
+
+    // Dropping a separately loaded model leaves the other models in the process usable
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_gpu_layers = 32;
+    drop(LModel::new(config).unwrap());
+    let mut config = LContextConfig::new(model.path());
+    config.n_ctx = 256;
+    let mut generator = LGenerator::new(LContext::with_model(&model, config).unwrap());
+    let output = generator
+        .generate(
+            prompts[0],
+            LGeneratorParams {
+                worker_thread_count: 4,
+                generate_tokens: 16,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!output.is_empty());
+}
@@ -0,0 +1,84 @@
+use llama_cpp_rs::{LContext, LContextConfig, LTokenizeOptions};
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.n_gpu_layers = 32;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+    let text = "[INST]What is the capital of France?[/INST]";
+
+    // BOS is added by default, and can be left off for text that continues a prompt
+    let tokens = context.tokenize(text).unwrap();
+    assert!(tokens.iter().next().unwrap().is_beginning_of_stream(&context));
+    let continuation = context
+        .tokenize_with(
+            text,
+            LTokenizeOptions {
+                add_bos: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(continuation.len(), tokens.len() - 1);
+    assert!(!continuation.iter().any(|token| token.is_beginning_of_stream(&context)));
+
+    // EOS can be added to the end
+    let terminated = context
+        .tokenize_with(
+            text,
+            LTokenizeOptions {
+                add_eos: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(terminated.len(), tokens.len() + 1);
+    assert!(terminated.iter().last().unwrap().is_end_of_stream(&context));
+
+    // Special tokens written in the text are plain text unless parsed
+    let turns = "<s>[INST]Hi[/INST]Hello</s><s>[INST]Bye[/INST]";
+    let plain = context
+        .tokenize_with(
+            turns,
+            LTokenizeOptions {
+                add_bos: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!plain.iter().any(|token| token.is_beginning_of_stream(&context)));
+    let parsed = context
+        .tokenize_with(
+            turns,
+            LTokenizeOptions {
+                add_bos: false,
+                parse_special: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    println!("{:?}", parsed);
+    assert_eq!(parsed.iter().filter(|token| token.is_beginning_of_stream(&context)).count(), 2);
+    assert_eq!(parsed.iter().filter(|token| token.is_end_of_stream(&context)).count(), 1);
+
+    // Only the start of the text gets the SentencePiece leading space, not the text after each special token
+    let special = LTokenizeOptions {
+        parse_special: true,
+        ..Default::default()
+    };
+    for text in ["A</s>B", "Hello</s>World", "Hi</s>\nthere</s> you"] {
+        let tokens = context.tokenize_with(text, special).unwrap();
+        println!("{:?}", tokens);
+        assert_eq!(context.detokenize(&tokens).unwrap(), text.replace("</s>", ""));
+    }
+
+    // Text that needs more tokens than bytes / 2 is sized exactly on the second pass
+    let emoji = "🦀🦀🦀🦀🦀🦀🦀🦀";
+    let emoji_tokens = context.tokenize(emoji).unwrap();
+    assert!(emoji_tokens.len() > 1);
+    assert_eq!(context.detokenize(&emoji_tokens).unwrap(), emoji);
+}
@@ -0,0 +1,46 @@
+use llama_cpp_rs::{LContext, LContextConfig, LTokenType};
+
+#[test]
+pub fn main() {
+    // Setup params
+    let mut config = LContextConfig::new("models/model.gguf");
+    config.n_ctx = 512;
+    config.vocab_only = true;
+
+    // Load model
+    let context = LContext::new(config).unwrap();
+    let vocab = context.vocab();
+    println!("{} tokens", vocab.len());
+    assert_eq!(vocab.len(), context.n_vocab());
+
+    // Every token has an entry; a token whose piece can't be rendered is an error, not a gap
+    assert_eq!(vocab.iter().count(), vocab.len());
+    assert!(vocab.iter().all(|entry| entry.is_ok()));
+
+    // Special tokens
+    let bos = vocab.get(vocab.bos().id()).unwrap().unwrap();
+    let eos = vocab.get(vocab.eos().id()).unwrap().unwrap();
+    println!("bos {:?}\neos {:?}", bos, eos);
+    assert_eq!(bos.token_type, LTokenType::Control);
+    assert_eq!(eos.token_type, LTokenType::Control);
+    assert_eq!(vocab.text(&vocab.bos()), Some(bos.text.clone()));
+    assert!(vocab.control_tokens().any(|token| token == vocab.bos()));
+    assert_eq!(vocab.get(vocab.newline().id()).unwrap().unwrap().piece, b"\n");
+    println!("infill prefix {:?}", vocab.infill_prefix());
+
+    // Reverse lookup from a piece agrees with the tokenizer
+    let paris = vocab.find_piece(b" Paris").unwrap();
+    let tokens = context.tokenize("Paris").unwrap();
+    assert_eq!(tokens.iter().last().unwrap(), paris);
+    assert_eq!(vocab.find_text("▁Paris"), Some(paris.clone()));
+    assert_eq!(vocab.find_text(&bos.text), Some(vocab.bos()));
+    assert!(vocab.get(paris.id()).unwrap().unwrap().score != 0f32);
+    assert!(vocab.find_piece(b"not a single token at all").is_none());
+    assert!(vocab.get(vocab.len()).unwrap().is_none());
+
+    // A piece that is also a byte token is found as the normal token the tokenizer produces
+    let space = vocab.find_piece(b" ").unwrap();
+    assert_ne!(vocab.get(space.id()).unwrap().unwrap().token_type, LTokenType::Byte);
+    let vocab_again = context.vocab();
+    assert_eq!(vocab_again.find_piece(b" "), Some(space));
+}